@@ -50,4 +50,85 @@ pub enum SwapError {
 
     #[msg("The deposit single token amount is too small")]
     DepositSingleAmountTooSmall,
+
+    #[msg("Token A mint must sort before Token B mint")]
+    InvalidMintOrder,
+
+    #[msg("Only the pool admin can perform this action")]
+    Unauthorized,
+
+    #[msg("This pool operation is currently paused")]
+    PoolPaused,
+
+    #[msg("Unknown pause flags")]
+    InvalidPauseFlags,
+
+    #[msg("Unknown swap curve type")]
+    InvalidCurveType,
+
+    #[msg("Amplification coefficient is out of range")]
+    InvalidAmpFactor,
+
+    #[msg("Host fee exceeds maximum allowed limit")]
+    HostFeeTooHigh,
+
+    #[msg("Observation cardinality exceeds the maximum")]
+    InvalidObservationCardinality,
+
+    #[msg("Flash fee exceeds maximum allowed limit")]
+    FlashFeeTooHigh,
+
+    #[msg("The pool is locked by an outstanding flash loan")]
+    PoolLocked,
+
+    #[msg("Flash loan amount is invalid")]
+    InvalidFlashLoanAmount,
+
+    #[msg("Flash loan must be followed by a repay instruction in the same transaction")]
+    FlashLoanRepayMissing,
+
+    #[msg("Flash loan cannot be invoked via CPI")]
+    FlashLoanCpiNotAllowed,
+
+    #[msg("No outstanding flash loan")]
+    FlashLoanNotActive,
+
+    #[msg("Flash loan was not fully repaid")]
+    FlashLoanNotRepaid,
+
+    #[msg("Flash swap callback did not pay enough input tokens")]
+    FlashSwapUnderpaid,
+
+    #[msg("Invalid swap route")]
+    InvalidRoute,
+
+    #[msg("Transaction deadline exceeded")]
+    DeadlineExceeded,
+
+    #[msg("Pool mints must not have a freeze authority")]
+    MintHasFreezeAuthority,
+
+    #[msg("Pool mints must not have a permanent delegate")]
+    MintHasPermanentDelegate,
+
+    #[msg("Pool mints must be transferable")]
+    MintNonTransferable,
+
+    #[msg("Pool mints must not default new accounts to frozen")]
+    MintDefaultAccountStateFrozen,
+
+    #[msg("Pool mints must not enable confidential transfers")]
+    MintHasConfidentialTransfer,
+
+    #[msg("LP metadata requires the Token-2022 program")]
+    LpMetadataRequiresToken2022,
+
+    #[msg("Pool invariant violated")]
+    InvariantViolated,
+
+    #[msg("New admin must be a non-default key different from the current admin")]
+    InvalidAdmin,
+
+    #[msg("LP metadata uri exceeds the length limit")]
+    InvalidLpMetadata,
 }
@@ -71,4 +71,104 @@ pub struct SwapEvent {
     pub to_mint: Pubkey,
     pub amount_in: u64,
     pub amount_out: u64,
-}
\ No newline at end of file
+    pub owner_fee: u64,
+    pub owner_fee_pool_tokens: u64,
+    pub host_fee_pool_tokens: u64,
+}
+
+
+/// 手续费更新
+#[event]
+pub struct FeesUpdatedEvent {
+    pub swap: Pubkey,
+    pub admin: Pubkey,
+    pub old_trade_fees: u16,
+    pub old_withdraw_fees: u16,
+    pub old_owner_trade_fees: u16,
+    pub old_host_fees: u16,
+    pub old_flash_fees: u16,
+    pub trade_fees: u16,
+    pub withdraw_fees: u16,
+    pub owner_trade_fees: u16,
+    pub host_fees: u16,
+    pub flash_fees: u16,
+}
+
+
+/// 暂停状态更新
+#[event]
+pub struct PauseUpdatedEvent {
+    pub swap: Pubkey,
+    pub admin: Pubkey,
+    pub old_paused: u8,
+    pub paused: u8,
+}
+
+
+/// 管理员转移
+#[event]
+pub struct AdminUpdatedEvent {
+    pub swap: Pubkey,
+    pub old_admin: Pubkey,
+    pub admin: Pubkey,
+}
+
+
+/// 闪电贷
+#[event]
+pub struct FlashLoanEvent {
+    pub swap: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub fee_a: u64,
+    pub fee_b: u64,
+}
+
+
+/// 闪电兑换
+#[event]
+pub struct FlashSwapEvent {
+    pub swap: Pubkey,
+    pub user: Pubkey,
+    pub callback_program: Pubkey,
+    pub a_to_b: bool,
+    pub amount_out: u64,
+    pub amount_in_a: u64,
+    pub amount_in_b: u64,
+    pub owner_fee_pool_tokens: u64,
+}
+
+
+/// 多跳路由兑换汇总，每一跳另有一个 `SwapEvent`
+#[event]
+pub struct RouteSwapEvent {
+    pub user: Pubkey,
+    pub from_mint: Pubkey,
+    pub to_mint: Pubkey,
+    pub hops: u8,
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+
+/// 转出金库中超出储备的代币
+#[event]
+pub struct SkimEvent {
+    pub swap: Pubkey,
+    pub admin: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+
+/// 以金库余额重置储备
+#[event]
+pub struct SyncEvent {
+    pub swap: Pubkey,
+    pub admin: Pubkey,
+    pub old_reserve_a: u64,
+    pub old_reserve_b: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
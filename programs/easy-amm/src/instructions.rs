@@ -5,6 +5,16 @@ pub mod withdraw_single;
 pub mod deposit;
 pub mod deposit_single;
 pub mod exchange;
+pub mod update_fees;
+pub mod set_pause;
+pub mod set_admin;
+pub mod increase_observation_cardinality;
+pub mod flash_loan;
+pub mod repay_flash_loan;
+pub mod flash_swap;
+pub mod route_swap;
+pub mod skim;
+pub mod sync;
 
 
 pub use initialize_swap::*;
@@ -12,4 +22,14 @@ pub use withdraw_all::*;
 pub use withdraw_single::*;
 pub use deposit::*;
 pub use deposit_single::*;
-pub use exchange::*;
\ No newline at end of file
+pub use exchange::*;
+pub use update_fees::*;
+pub use set_pause::*;
+pub use set_admin::*;
+pub use increase_observation_cardinality::*;
+pub use flash_loan::*;
+pub use repay_flash_loan::*;
+pub use flash_swap::*;
+pub use route_swap::*;
+pub use skim::*;
+pub use sync::*;
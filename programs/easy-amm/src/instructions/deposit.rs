@@ -3,7 +3,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
 
-use crate::{error::SwapError, events::DepositEvent, shared::{mint_tokens, pool_tokens_to_trading_tokens, to_u64, transfer_tokens}, state::Swap};
+use crate::{error::SwapError, events::DepositEvent, shared::{amount_before_transfer_fee, check_deadline, mint_tokens, PoolInvariant, to_u64, transfer_token_infos, unwrap_native, UserTokenAccount, wrap_native}, state::{Observations, Swap}};
 
 
 #[derive(Accounts)]
@@ -12,8 +12,15 @@ pub struct Deposit<'info> {
     pub user: Signer<'info>,
 
     #[account(
-        seeds = [Swap::SWAP_SEEDS],
-        bump
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_DEPOSIT) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
     )]
     pub swap: Account<'info, Swap>,
 
@@ -27,19 +34,31 @@ pub struct Deposit<'info> {
     )]
     pub token_b_mint: InterfaceAccount<'info, Mint>,
 
+    /// CHECK: user 在 token_a_mint 下的 ATA，不存在时在 process 中创建
     #[account(
         mut,
-        associated_token::mint = token_a_mint,
-        associated_token::authority = user
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_a_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_a: UncheckedAccount<'info>,
 
+    /// CHECK: user 在 token_b_mint 下的 ATA，不存在时在 process 中创建
     #[account(
         mut,
-        associated_token::mint = token_b_mint,
-        associated_token::authority = user
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_b_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_b: UncheckedAccount<'info>,
 
     #[account(
         mut,
@@ -70,98 +89,161 @@ pub struct Deposit<'info> {
             Swap::POOL_MINT_SEEDS
         ],
         bump = swap.pool_mint_bump_seed,
-        mint::authority = swap
+        mint::authority = swap,
+        mint::token_program = lp_token_program
     )]
     pub pool_mint: InterfaceAccount<'info, Mint>,
 
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
     #[account(
         init_if_needed,
         payer = user,
         associated_token::mint = pool_mint,
-        associated_token::authority = user
+        associated_token::authority = user,
+        associated_token::token_program = lp_token_program
     )]
     pub user_mint_account: InterfaceAccount<'info, TokenAccount>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>
 }
 
 
 impl<'info> Deposit<'info> {
     pub fn process(
-        &self,
-        bump_swap: u8,
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         pool_token_amount: u64,
         maximum_token_a_amount: u64,
         maximum_token_b_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        // 以操作前的储备更新 TWAP 累计价格
+        self.swap.update_price_oracle(&self.observations)?;
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
         require_gt!(
             pool_token_amount, 
             Swap::MIN_TOKEN_AMOUNT, 
             SwapError::DepositPoolTokenAmountTooSmall
         );
 
-        let (token_a_amount, token_b_amount) = pool_tokens_to_trading_tokens(
+        let (token_a_amount, token_b_amount) = self.swap.curve()?.pool_tokens_to_trading_tokens(
             true,
             u128::from(pool_token_amount), 
             u128::from(self.pool_mint.supply), 
-            u128::from(self.token_a.amount), 
-            u128::from(self.token_b.amount)
+            u128::from(self.swap.reserve_a), 
+            u128::from(self.swap.reserve_b)
         ).ok_or(SwapError::ZeroTradingTokens)?;
 
-        let token_a_amount = to_u64(token_a_amount)?;
-        let token_b_amount = to_u64(token_b_amount)?;
+        if token_a_amount == 0 || token_b_amount == 0 {
+            return err!(SwapError::ZeroTradingTokens);
+        }
+
+        // 用户需要转出的数量(扣除转账手续费后池子正好收到所需数量)
+        let (received_a, received_b) = (to_u64(token_a_amount)?, to_u64(token_b_amount)?);
+        let token_a_amount = amount_before_transfer_fee(received_a, &self.token_a_mint)?;
+        let token_b_amount = amount_before_transfer_fee(received_b, &self.token_b_mint)?;
 
         if token_a_amount > maximum_token_a_amount 
             || token_b_amount > maximum_token_b_amount 
         {
             return err!(SwapError::ExceededSlippage);
         }
-
-        if token_a_amount == 0 || token_b_amount == 0 {
-            return err!(SwapError::ZeroTradingTokens);
-        }
         
-        if token_a_amount > self.user_token_a.amount 
-            || token_b_amount > self.user_token_b.amount 
-        {
+        // 用户 ATA 不存在时创建，原生 SOL 一侧自动包装不足部分
+        let user_token_a = UserTokenAccount::load_or_create(
+            self.user_token_a.to_account_info(), 
+            &self.user, 
+            &self.token_a_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        let user_token_b = UserTokenAccount::load_or_create(
+            self.user_token_b.to_account_info(), 
+            &self.user, 
+            &self.token_b_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        let token_a_balance = wrap_native(
+            &self.user, 
+            &user_token_a, 
+            token_a_amount, 
+            &self.token_program, 
+            &self.system_program
+        )?;
+        let token_b_balance = wrap_native(
+            &self.user, 
+            &user_token_b, 
+            token_b_amount, 
+            &self.token_program, 
+            &self.system_program
+        )?;
+        if token_a_amount > token_a_balance || token_b_amount > token_b_balance {
             return err!(SwapError::InsufficientTokenBalance);
         }
 
+        self.swap.deposit_reserves(received_a, received_b)?;
+
         // 转账
-        transfer_tokens(
-            &self.user_token_a, 
-            &self.token_a, 
+        transfer_token_infos(
+            user_token_a.to_account_info(), 
+            self.token_a.to_account_info(), 
             token_a_amount, 
             &self.token_a_mint, 
             self.user.to_account_info(), 
             &self.token_program, 
-            None
+            None, 
+            remaining_accounts
         )?;
         msg!("双币存入 token_a: {}", token_a_amount);
 
-        transfer_tokens(
-            &self.user_token_b, 
-            &self.token_b, 
+        transfer_token_infos(
+            user_token_b.to_account_info(), 
+            self.token_b.to_account_info(), 
             token_b_amount, 
             &self.token_b_mint, 
             self.user.to_account_info(), 
             &self.token_program, 
-            None
+            None, 
+            remaining_accounts
         )?;
         msg!("双币存入 token_b: {}", token_b_amount);
 
+        // 本指令新建的 wSOL 账户关闭，退回租金
+        unwrap_native(&self.user, &user_token_a, &self.token_program)?;
+        unwrap_native(&self.user, &user_token_b, &self.token_program)?;
+
         // 代币铸造
         mint_tokens(
             &self.pool_mint, 
             &self.user_mint_account, 
             pool_token_amount, 
             self.swap.to_account_info(), 
-            &self.token_program, 
+            &self.lp_token_program, 
             &[&[
                 Swap::SWAP_SEEDS,
-                &[bump_swap]
+                self.swap.token_a_mint.as_ref(),
+                self.swap.token_b_mint.as_ref(),
+                &[self.swap.swap_bump_seed]
             ]]
         )?;
         msg!("池币铸造(双币存入): {}", pool_token_amount);
@@ -174,6 +256,6 @@ impl<'info> Deposit<'info> {
             token_b_amount,
         });
 
-        Ok(())
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
     }
 }
@@ -1,7 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
 
-use crate::{error::SwapError, events::DepositSingleEvent, shared::{deposit_single_token_type, mint_tokens, to_u64, transfer_tokens}, state::Swap};
+use crate::{error::SwapError, events::DepositSingleEvent, shared::{amount_after_transfer_fee, amount_before_transfer_fee, check_deadline, interest_scale, mint_tokens, PoolInvariant, scale_interest_amount, to_u64, transfer_token_infos, unscale_interest_amount, unwrap_native, UserTokenAccount, wrap_native}, state::{Observations, Swap}};
 
 
 #[derive(Accounts)]
@@ -10,26 +10,62 @@ pub struct DepositSingle<'info> {
     pub user: Signer<'info>,
 
     #[account(
-        seeds = [Swap::SWAP_SEEDS],
-        bump
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_DEPOSIT) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
     )]
     pub swap: Account<'info, Swap>,
 
     pub mint: InterfaceAccount<'info, Mint>,
 
+    /// 池子另一侧代币的 mint，用于按计息扩展换算含息数量
+    #[account(
+        constraint = (mint.key() == swap.token_a_mint && other_mint.key() == swap.token_b_mint)
+            || (mint.key() == swap.token_b_mint && other_mint.key() == swap.token_a_mint)
+            @ SwapError::InvalidMint
+    )]
+    pub other_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// CHECK: user 在 mint 下的 ATA，不存在时在 process 中创建
     #[account(
         mut,
-        associated_token::mint = mint,
-        associated_token::authority = user
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
+    )]
+    pub user_token: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
     )]
-    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        token::mint = mint,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
         token::authority = swap
     )]
-    pub pool_token: InterfaceAccount<'info, TokenAccount>,
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
@@ -38,48 +74,61 @@ pub struct DepositSingle<'info> {
             Swap::POOL_MINT_SEEDS
         ],
         bump = swap.pool_mint_bump_seed,
-        mint::authority = swap
+        mint::authority = swap,
+        mint::token_program = lp_token_program
     )]
     pub pool_mint: InterfaceAccount<'info, Mint>,
 
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
     #[account(
         init_if_needed,
         payer = user,
         associated_token::mint = pool_mint,
-        associated_token::authority = user
+        associated_token::authority = user,
+        associated_token::token_program = lp_token_program
     )]
     pub user_mint_account: InterfaceAccount<'info, TokenAccount>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>
 }
 
 
 impl<'info> DepositSingle<'info> {
     pub fn process(
-        &self,
-        bump_swap: u8,
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         source_token_amount: u64,
-        minimum_pool_token_amount: u64
+        minimum_pool_token_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
-        require_gt!(source_token_amount, 0, SwapError::DepositSingleAmountTooSmall);
-
-        if self.mint.key() != self.swap.token_a_mint 
-            && self.mint.key() != self.swap.token_b_mint 
-        {
-            return err!(SwapError::InvalidMint);
-        }
+        let (invariant, is_token_a) = self.prepare(deadline)?;
 
-        if source_token_amount > self.user_token.amount {
-            return err!(SwapError::InsufficientTokenBalance);
-        }
+        require_gt!(source_token_amount, 0, SwapError::DepositSingleAmountTooSmall);
+        let (reserve_source, reserve_other) = self.swap.reserves(is_token_a);
 
-        // 计算能兑换到的 池币
-        let pool_token_amount = deposit_single_token_type(
+        // 按池子扣除转账手续费后实际收到的数量计算能兑换到的池币(换算成含息数量)
+        let received_amount = amount_after_transfer_fee(source_token_amount, &self.mint)?;
+        let source_scale = interest_scale(&self.mint)?;
+        let other_scale = interest_scale(&self.other_mint)?;
+        let pool_token_amount = self.swap.curve()?.deposit_single_token_type(
             u128::from(self.swap.trade_fees), 
-            u128::from(source_token_amount), 
-            u128::from(self.pool_token.amount), 
+            scale_interest_amount(received_amount, source_scale, false)?, 
+            scale_interest_amount(reserve_source, source_scale, false)?, 
+            scale_interest_amount(reserve_other, other_scale, false)?, 
             u128::from(self.pool_mint.supply)
         ).ok_or(SwapError::ZeroTradingTokens)?;
 
@@ -93,28 +142,143 @@ impl<'info> DepositSingle<'info> {
             return err!(SwapError::ZeroTradingTokens);
         }
 
+        self.finish_deposit_single(
+            remaining_accounts, 
+            invariant, 
+            is_token_a, 
+            source_token_amount, 
+            received_amount, 
+            pool_token_amount
+        )
+    }
+
+    /// 精确输出单币存入：铸造 `pool_token_amount` 池币，按曲线反算需要存入的单币数量
+    /// (含“一半”兑换的交易手续费与转账手续费)，不超过 `maximum_source_token_amount`
+    pub fn process_exact_out(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        pool_token_amount: u64,
+        maximum_source_token_amount: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        let (invariant, is_token_a) = self.prepare(deadline)?;
+
+        require_gt!(pool_token_amount, 0, SwapError::ZeroTradingTokens);
+        let (reserve_source, reserve_other) = self.swap.reserves(is_token_a);
+
+        // 池子需要收到的数量(换算成含息数量计算，向上取整)
+        let source_scale = interest_scale(&self.mint)?;
+        let other_scale = interest_scale(&self.other_mint)?;
+        let required_amount = self.swap.curve()?.deposit_single_token_type_exact_out(
+            u128::from(self.swap.trade_fees), 
+            u128::from(pool_token_amount), 
+            scale_interest_amount(reserve_source, source_scale, false)?, 
+            scale_interest_amount(reserve_other, other_scale, false)?, 
+            u128::from(self.pool_mint.supply)
+        ).ok_or(SwapError::ZeroTradingTokens)?;
+        let required_amount = unscale_interest_amount(required_amount, source_scale, true)?;
+
+        // 用户需要转出的数量(池子扣除转账手续费后至少收到 required_amount)
+        let source_token_amount = amount_before_transfer_fee(required_amount, &self.mint)?;
+        if source_token_amount > maximum_source_token_amount {
+            return err!(SwapError::ExceededSlippage);
+        }
+
+        let received_amount = amount_after_transfer_fee(source_token_amount, &self.mint)?;
+        self.finish_deposit_single(
+            remaining_accounts, 
+            invariant, 
+            is_token_a, 
+            source_token_amount, 
+            received_amount, 
+            pool_token_amount
+        )
+    }
+
+    /// 两种模式共用的前置步骤：检查截止时间，以操作前的储备更新 TWAP 累计价格，
+    /// 返回操作前的不变量快照以及存入的是否为 token_a
+    fn prepare(&mut self, deadline: Option<i64>) -> Result<(PoolInvariant, bool)> {
+        check_deadline(deadline)?;
+
+        self.swap.update_price_oracle(&self.observations)?;
+        if self.mint.key() == self.swap.token_a_mint {
+            let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.mint, &self.other_mint)?;
+            Ok((invariant, true))
+        } else if self.mint.key() == self.swap.token_b_mint {
+            let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.other_mint, &self.mint)?;
+            Ok((invariant, false))
+        } else {
+            err!(SwapError::InvalidMint)
+        }
+    }
+
+    /// 两种模式共用的结算：包装原生 SOL、用户转入 `source_token_amount`(池子实际收到
+    /// `received_amount`)、铸造 `pool_token_amount` 池币，发出事件后校验不变量
+    fn finish_deposit_single(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        invariant: PoolInvariant,
+        is_token_a: bool,
+        source_token_amount: u64,
+        received_amount: u64,
+        pool_token_amount: u64,
+    ) -> Result<()> {
+        // 用户 ATA 不存在时创建，原生 SOL 自动包装不足部分
+        let user_token = UserTokenAccount::load_or_create(
+            self.user_token.to_account_info(), 
+            &self.user, 
+            &self.mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        let source_balance = wrap_native(
+            &self.user, 
+            &user_token, 
+            source_token_amount, 
+            &self.token_program, 
+            &self.system_program
+        )?;
+        if source_token_amount > source_balance {
+            return err!(SwapError::InsufficientTokenBalance);
+        }
+
+        let pool_token = if is_token_a {
+            self.swap.deposit_reserves(received_amount, 0)?;
+            &self.token_a
+        } else {
+            self.swap.deposit_reserves(0, received_amount)?;
+            &self.token_b
+        };
+
         // 转账
-        transfer_tokens(
-            &self.user_token, 
-            &self.pool_token, 
+        transfer_token_infos(
+            user_token.to_account_info(), 
+            pool_token.to_account_info(), 
             source_token_amount, 
             &self.mint, 
             self.user.to_account_info(), 
             &self.token_program, 
-            None
+            None, 
+            remaining_accounts
         )?;
         msg!("转账(单币存入): {}", source_token_amount);
 
+        // 本指令新建的 wSOL 账户关闭，退回租金
+        unwrap_native(&self.user, &user_token, &self.token_program)?;
+
         // 铸币
         mint_tokens(
             &self.pool_mint, 
             &self.user_mint_account, 
             pool_token_amount, 
             self.swap.to_account_info(), 
-            &self.token_program, 
+            &self.lp_token_program, 
             &[&[
                 Swap::SWAP_SEEDS,
-                &[bump_swap]
+                self.swap.token_a_mint.as_ref(),
+                self.swap.token_b_mint.as_ref(),
+                &[self.swap.swap_bump_seed]
             ]]
         )?;
         msg!("铸币(单币存入): {}", pool_token_amount);
@@ -126,6 +290,6 @@ impl<'info> DepositSingle<'info> {
             pool_token_amount,
         });
 
-        Ok(())
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
     }
 }
@@ -3,21 +3,27 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_2022::{
-        spl_token_2022::{
-            extension::{
-                transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions,
-            },
-            state::Mint as Mint_2022,
-        },
-        ID as TOKEN_2022_PROGRAM_ID,
-    },
     token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
-use crate::{error::SwapError, events::SwapEvent, state::Swap};
+use crate::{error::SwapError, events::SwapEvent, state::{Observations, Swap}};
 
-use super::shared::{calculate_exchange_amount, to_u64, transfer_tokens};
+use super::shared::{
+    amount_after_transfer_fee, 
+    amount_before_transfer_fee, 
+    calculation_fee, 
+    check_deadline, 
+    interest_scale, 
+    mint_owner_fee, 
+    PoolInvariant, 
+    scale_interest_amount, 
+    to_u64, 
+    transfer_token_infos, 
+    unscale_interest_amount, 
+    unwrap_native, 
+    UserTokenAccount, 
+    wrap_native
+};
 
 #[derive(Accounts)]
 pub struct Exchange<'info> {
@@ -25,8 +31,15 @@ pub struct Exchange<'info> {
     pub user: Signer<'info>,
 
     #[account(
-        seeds = [Swap::SWAP_SEEDS],
-        bump
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_SWAP) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
     )]
     pub swap: Account<'info, Swap>,
 
@@ -62,45 +75,225 @@ pub struct Exchange<'info> {
     )]
     pub token_b: InterfaceAccount<'info, TokenAccount>,
 
+    /// CHECK: user 在 token_a_mint 下的 ATA，不存在时在 process 中创建
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = token_a_mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_a_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token_a: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_a: UncheckedAccount<'info>,
 
+    /// CHECK: user 在 token_b_mint 下的 ATA，不存在时在 process 中创建
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = token_b_mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_b_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token_b: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_b: UncheckedAccount<'info>,
 
     #[account(
+        mut,
         seeds = [
             swap.key().as_ref(),
             Swap::POOL_MINT_SEEDS
         ],
         bump = swap.pool_mint_bump_seed,
-        mint::authority = swap
+        mint::authority = swap,
+        mint::token_program = lp_token_program
     )]
     pub pool_mint: InterfaceAccount<'info, Mint>,
 
+    #[account(
+        mut,
+        address = swap.pool_fee_account,
+        token::mint = swap.pool_mint,
+        token::token_program = lp_token_program
+    )]
+    pub pool_fee_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// 可选的推荐方池币账户，传入时分得 `host_fees` 比例的协议手续费
+    #[account(
+        mut,
+        token::mint = swap.pool_mint,
+        token::token_program = lp_token_program
+    )]
+    pub host_fee_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
 impl<'info> Exchange<'info> {
     pub fn process(
-        &self,
-        bump_swap: u8,
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         a_to_b: bool,
         amount_in: u64,
         minimum_amount_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        let invariant = self.prepare(deadline)?;
+        let (reserve_source, reserve_destination) = self.swap.reserves(a_to_b);
+        let (source_mint, destination_mint) = if a_to_b {
+            (&self.token_a_mint, &self.token_b_mint)
+        } else {
+            (&self.token_b_mint, &self.token_a_mint)
+        };
+
+        // 计算扣除转账手续费后的 amount_in
+        let actual_amount_in = amount_after_transfer_fee(amount_in, source_mint)?;
+
+        // 初步计算实际参与兑换和能兑换到的代币数量(按计息扩展换算成含息数量定价)
+        let source_scale = interest_scale(source_mint)?;
+        let destination_scale = interest_scale(destination_mint)?;
+        let (source_amount_swapped, destination_amount_swapped) = self.swap.curve()?.swap(
+            self.swap.total_trade_fees(), 
+            scale_interest_amount(actual_amount_in, source_scale, false)?, 
+            scale_interest_amount(reserve_source, source_scale, false)?, 
+            scale_interest_amount(reserve_destination, destination_scale, false)?
+        ).ok_or(SwapError::ZeroTradingTokens)?;
+
+        // 计算用户实际需要支付的 token(扣除转账手续费后池子正好收到 source_amount_swapped)
+        let source_amount_swapped = unscale_interest_amount(source_amount_swapped, source_scale, true)?;
+        let source_transfer_amount = amount_before_transfer_fee(source_amount_swapped, source_mint)?;
+        // 换算回原始数量与反推转账手续费都向上取整，结果不能超过用户给出的 amount_in
+        require!(source_transfer_amount <= amount_in, SwapError::ExceededSlippage);
+
+        // 以用户扣除转账手续费后实际收到的数量判断是否超过最小兑换量
+        let destination_transfer_amount = unscale_interest_amount(destination_amount_swapped, destination_scale, false)?;
+        let amount_received = amount_after_transfer_fee(destination_transfer_amount, destination_mint)?;
+        if amount_received < minimum_amount_out {
+            return err!(SwapError::ExceededSlippage);
+        }
+
+        self.settle(
+            remaining_accounts, 
+            a_to_b, 
+            invariant, 
+            source_amount_swapped, 
+            source_transfer_amount, 
+            destination_transfer_amount, 
+            source_scale, 
+            destination_scale
+        )
+    }
+
+    /// 精确输出兑换：用户指定想要得到的目标代币数量，最多支付 `maximum_amount_in`
+    pub fn process_exact_out(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        a_to_b: bool,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        let invariant = self.prepare(deadline)?;
+
+        require_gt!(amount_out, 0, SwapError::ZeroTradingTokens);
+        let (reserve_source, reserve_destination) = self.swap.reserves(a_to_b);
+        let (source_mint, destination_mint) = if a_to_b {
+            (&self.token_a_mint, &self.token_b_mint)
+        } else {
+            (&self.token_b_mint, &self.token_a_mint)
+        };
+
+        // 池子需要转出的数量(用户扣除转账手续费后正好收到 amount_out)
+        let destination_transfer_amount = amount_before_transfer_fee(amount_out, destination_mint)?;
+
+        // 反向计算池子需要收到的源代币数量(按计息扩展换算成含息数量定价)
+        let source_scale = interest_scale(source_mint)?;
+        let destination_scale = interest_scale(destination_mint)?;
+        let (source_amount_swapped, _) = self.swap.curve()?.swap_exact_out(
+            self.swap.total_trade_fees(), 
+            scale_interest_amount(destination_transfer_amount, destination_scale, true)?, 
+            scale_interest_amount(reserve_source, source_scale, false)?, 
+            scale_interest_amount(reserve_destination, destination_scale, false)?
+        ).ok_or(SwapError::ZeroTradingTokens)?;
+
+        // 用户需要转出的数量(扣除转账手续费后池子正好收到 source_amount_swapped)
+        let source_amount_swapped = unscale_interest_amount(source_amount_swapped, source_scale, true)?;
+        let source_transfer_amount = amount_before_transfer_fee(source_amount_swapped, source_mint)?;
+
+        if source_transfer_amount > maximum_amount_in {
+            return err!(SwapError::ExceededSlippage);
+        }
+
+        self.settle(
+            remaining_accounts, 
+            a_to_b, 
+            invariant, 
+            source_amount_swapped, 
+            source_transfer_amount, 
+            destination_transfer_amount, 
+            source_scale, 
+            destination_scale
+        )
+    }
+
+    /// 两种模式共用的前置步骤：检查截止时间，以操作前的储备更新 TWAP 累计价格，
+    /// 返回操作前的不变量快照
+    fn prepare(&mut self, deadline: Option<i64>) -> Result<PoolInvariant> {
+        check_deadline(deadline)?;
+
+        self.swap.update_price_oracle(&self.observations)?;
+        PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)
+    }
+
+    /// 两种模式共用的结算：包装原生 SOL、更新储备、双向转账、铸造协议手续费池币，
+    /// 发出事件后校验不变量
+    #[allow(clippy::too_many_arguments)]
+    fn settle(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        a_to_b: bool,
+        invariant: PoolInvariant,
+        source_amount_swapped: u64,
+        source_transfer_amount: u64,
+        destination_transfer_amount: u64,
+        source_scale: u128,
+        destination_scale: u128,
     ) -> Result<()> {
+        // 用户 ATA 不存在时创建
+        let user_token_a = UserTokenAccount::load_or_create(
+            self.user_token_a.to_account_info(), 
+            &self.user, 
+            &self.token_a_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        let user_token_b = UserTokenAccount::load_or_create(
+            self.user_token_b.to_account_info(), 
+            &self.user, 
+            &self.token_b_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
         let (
             user_source_token,
             user_destination_token,
@@ -110,8 +303,8 @@ impl<'info> Exchange<'info> {
             destination_mint,
         ) = if a_to_b {
             (
-                &self.user_token_a,
-                &self.user_token_b,
+                &user_token_a,
+                &user_token_b,
                 &self.token_a,
                 &self.token_b,
                 &self.token_a_mint,
@@ -119,8 +312,8 @@ impl<'info> Exchange<'info> {
             )
         } else {
             (
-                &self.user_token_b,
-                &self.user_token_a,
+                &user_token_b,
+                &user_token_a,
                 &self.token_b,
                 &self.token_a,
                 &self.token_b_mint,
@@ -128,96 +321,81 @@ impl<'info> Exchange<'info> {
             )
         };
 
-        let source_mint_info = source_mint.to_account_info();
-        let source_mint_data = source_mint_info.data.borrow();
-        let destination_mint_info = destination_mint.to_account_info();
-        let destination_mint_data = destination_mint_info.data.borrow();
-        let mut a_mint_2022_data: Option<StateWithExtensions<Mint_2022>> = None;
-        let mut b_mint_2022_data: Option<StateWithExtensions<Mint_2022>> = None;
-
-        if source_mint_info.owner == &TOKEN_2022_PROGRAM_ID {
-            a_mint_2022_data = Some(StateWithExtensions::<Mint_2022>::unpack(&source_mint_data)?);
-        }
-
-        if destination_mint_info.owner == &TOKEN_2022_PROGRAM_ID {
-            b_mint_2022_data = Some(StateWithExtensions::<Mint_2022>::unpack(&destination_mint_data)?);
-        }
-
-        // 计算扣除转账手续费后的 amount_in
-        let actual_amount_in = if let Some(data) = &a_mint_2022_data {
-            Self::amount_after_transfer_fee(amount_in, data, true)?
-        } else {
-            amount_in
-        };
-
-        // 初步计算实际参与兑换和能兑换到的代币数量
-        let (source_amount_swapped, destination_amount_swapped) = calculate_exchange_amount(
-            u128::from(self.swap.trade_fees), 
-            u128::from(actual_amount_in), 
-            u128::from(pool_source_token.amount), 
-            u128::from(pool_destination_token.amount)
-        ).ok_or(SwapError::ZeroTradingTokens)?;
-
-        // 计算用户实际需要支付的 token
-        let source_amount_swapped = to_u64(source_amount_swapped)?;
-        let source_transfer_amount = if let Some(data) = &a_mint_2022_data {
-            Self::amount_after_transfer_fee(source_amount_swapped, data, false)?
-        } else {
-            source_amount_swapped
-        };
+        // 协议手续费(源代币计)
+        let owner_fee = to_u64(calculation_fee(
+            u128::from(source_amount_swapped), 
+            u128::from(self.swap.owner_trade_fees)
+        ).ok_or(SwapError::FeeCalculationFailure)?)?;
 
-        if source_transfer_amount > user_source_token.amount {
+        // 源代币为原生 SOL 时自动包装不足部分
+        let source_balance = wrap_native(
+            &self.user, 
+            user_source_token, 
+            source_transfer_amount, 
+            &self.token_program, 
+            &self.system_program
+        )?;
+        if source_transfer_amount > source_balance {
             return err!(SwapError::InsufficientTokenBalance)
         }
 
-        // 判断是否超过最小兑换量
-        let destination_amount_swapped = to_u64(destination_amount_swapped)?;
-        let destination_transfer_amount = if let Some(data) = &b_mint_2022_data {
-            let amount_received = Self::amount_after_transfer_fee(
-                destination_amount_swapped, 
-                data, 
-                false,
-            )?;
-
-            if amount_received < minimum_amount_out {
-                return err!(SwapError::ExceededSlippage);
-            }
-            amount_received
+        if a_to_b {
+            self.swap.deposit_reserves(source_amount_swapped, 0)?;
+            self.swap.withdraw_reserves(0, destination_transfer_amount)?;
         } else {
-            if destination_amount_swapped < minimum_amount_out {
-                return err!(SwapError::ExceededSlippage);
-            }
-            destination_amount_swapped
-        };
-
+            self.swap.deposit_reserves(0, source_amount_swapped)?;
+            self.swap.withdraw_reserves(destination_transfer_amount, 0)?;
+        }
 
         // 用户转账
-        transfer_tokens(
-            user_source_token, 
-            pool_source_token, 
+        transfer_token_infos(
+            user_source_token.to_account_info(), 
+            pool_source_token.to_account_info(), 
             source_transfer_amount, 
             source_mint, 
             self.user.to_account_info(), 
             &self.token_program, 
-            None
+            None, 
+            remaining_accounts
         )?;
         msg!("兑换(用户转账): {}", source_transfer_amount);
 
         // 池子转账
-        transfer_tokens(
-            pool_destination_token, 
-            user_destination_token, 
+        transfer_token_infos(
+            pool_destination_token.to_account_info(), 
+            user_destination_token.to_account_info(), 
             destination_transfer_amount, 
             destination_mint, 
             self.swap.to_account_info(), 
             &self.token_program, 
             Some(&[&[
                 Swap::SWAP_SEEDS,
-                &[bump_swap]
-            ]])
+                self.swap.token_a_mint.as_ref(),
+                self.swap.token_b_mint.as_ref(),
+                &[self.swap.swap_bump_seed]
+            ]]), 
+            remaining_accounts
         )?;
         msg!("兑换(池子转账): {}", destination_transfer_amount);
 
+        // 本指令新建的 wSOL 账户关闭：源代币一侧退回未用完的 SOL 与租金，目标代币一侧解包给用户
+        unwrap_native(&self.user, user_source_token, &self.token_program)?;
+        unwrap_native(&self.user, user_destination_token, &self.token_program)?;
+
+        let (reserve_source, reserve_destination) = self.swap.reserves(a_to_b);
+        let (owner_fee_pool_tokens, host_fee_pool_tokens) = mint_owner_fee(
+            &self.swap, 
+            &self.pool_mint, 
+            &self.pool_fee_account, 
+            self.host_fee_account.as_deref(), 
+            &self.lp_token_program, 
+            owner_fee, 
+            reserve_source, 
+            reserve_destination, 
+            source_scale, 
+            destination_scale
+        )?;
+
         emit!(SwapEvent {
             user: self.user.key(),
             user_source_token: user_source_token.key(),
@@ -228,28 +406,11 @@ impl<'info> Exchange<'info> {
             to_mint: destination_mint.key(),
             amount_in: source_transfer_amount,
             amount_out: destination_transfer_amount,
+            owner_fee,
+            owner_fee_pool_tokens,
+            host_fee_pool_tokens,
         });
 
-        Ok(())
-    }
-
-    fn amount_after_transfer_fee<'a>(
-        amount: u64,
-        data: &StateWithExtensions<'a, Mint_2022>,
-        sub: bool
-    ) -> Result<u64> {
-        let epoch = Clock::get()?.epoch;
-        if let Ok(transfer_fee_config) = data.get_extension::<TransferFeeConfig>() {
-            let fee = transfer_fee_config
-                .calculate_epoch_fee(epoch, amount)
-                .ok_or(SwapError::FeeCalculationFailure)?;
-            if sub {
-                Ok(amount.saturating_sub(fee))
-            } else {
-                Ok(amount.saturating_add(fee))
-            }
-        } else {
-            Ok(amount)
-        }
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
     }
 }
@@ -0,0 +1,233 @@
+//! 闪电贷
+//! 从金库借出代币，同一笔交易中之后必须调用 `repay_flash_loan` 归还本金和手续费
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+        sysvar::instructions::{
+            load_current_index_checked, 
+            load_instruction_at_checked, 
+            ID as INSTRUCTIONS_SYSVAR_ID
+        },
+    },
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{
+    error::SwapError, 
+    events::FlashLoanEvent, 
+    shared::{calculation_fee, PoolInvariant, to_u64, transfer_tokens}, 
+    state::{Observations, Swap}
+};
+
+
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_SWAP) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        address = swap.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        address = swap.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
+        token::authority = swap
+    )]
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            swap.key().as_ref(),
+            Swap::POOL_MINT_SEEDS
+        ],
+        bump = swap.pool_mint_bump_seed,
+        mint::authority = swap
+    )]
+    pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
+    /// 接收借出的 token_a
+    #[account(
+        mut,
+        token::mint = token_a_mint
+    )]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// 接收借出的 token_b
+    #[account(
+        mut,
+        token::mint = token_b_mint
+    )]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: 指令 sysvar，用于检查后续的归还指令
+    #[account(
+        address = INSTRUCTIONS_SYSVAR_ID
+    )]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+
+impl<'info> FlashLoan<'info> {
+    pub fn process(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        amount_a: u64,
+        amount_b: u64
+    ) -> Result<()> {
+        require!(amount_a > 0 || amount_b > 0, SwapError::InvalidFlashLoanAmount);
+        require!(
+            amount_a <= self.token_a.amount && amount_b <= self.token_b.amount,
+            SwapError::InvalidFlashLoanAmount
+        );
+        self.check_repay_instruction()?;
+
+        // 以借出前的储备更新 TWAP 累计价格
+        self.swap.update_price_oracle(&self.observations)?;
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
+        let fee_a = to_u64(calculation_fee(
+            u128::from(amount_a), 
+            u128::from(self.swap.flash_fees)
+        ).ok_or(SwapError::FeeCalculationFailure)?)?;
+        let fee_b = to_u64(calculation_fee(
+            u128::from(amount_b), 
+            u128::from(self.swap.flash_fees)
+        ).ok_or(SwapError::FeeCalculationFailure)?)?;
+
+        self.swap.flash_loan_locked = true;
+        self.swap.flash_loan_active = true;
+        self.swap.flash_loan_reserve_a = self.token_a.amount;
+        self.swap.flash_loan_reserve_b = self.token_b.amount;
+        self.swap.flash_loan_fee_a = fee_a;
+        self.swap.flash_loan_fee_b = fee_b;
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            Swap::SWAP_SEEDS,
+            self.swap.token_a_mint.as_ref(),
+            self.swap.token_b_mint.as_ref(),
+            &[self.swap.swap_bump_seed]
+        ]];
+
+        if amount_a > 0 {
+            transfer_tokens(
+                &self.token_a, 
+                &self.user_token_a, 
+                amount_a, 
+                &self.token_a_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+            msg!("闪电贷借出 token_a: {}, 手续费: {}", amount_a, fee_a);
+        }
+
+        if amount_b > 0 {
+            transfer_tokens(
+                &self.token_b, 
+                &self.user_token_b, 
+                amount_b, 
+                &self.token_b_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+            msg!("闪电贷借出 token_b: {}, 手续费: {}", amount_b, fee_b);
+        }
+
+        emit!(FlashLoanEvent {
+            swap: self.swap.key(),
+            user: self.user.key(),
+            amount_a,
+            amount_b,
+            fee_a,
+            fee_b,
+        });
+
+        // 储备不变，借出部分由之后的 `repay_flash_loan` 归还
+        invariant.check_lent(
+            &self.swap, 
+            &mut self.token_a, 
+            &mut self.token_b, 
+            &mut self.pool_mint, 
+            amount_a, 
+            amount_b
+        )
+    }
+
+    /// 当前指令必须是顶层调用，且之后存在针对同一池子的 `repay_flash_loan`
+    fn check_repay_instruction(&self) -> Result<()> {
+        // 指令 sysvar 中的当前指令在自身 CPI 时同样指向本程序，需另外检查调用栈高度
+        require_eq!(get_stack_height(), TRANSACTION_LEVEL_STACK_HEIGHT, SwapError::FlashLoanCpiNotAllowed);
+
+        let instructions = self.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)?;
+
+        let current = load_instruction_at_checked(usize::from(current_index), &instructions)?;
+        require_keys_eq!(current.program_id, crate::ID, SwapError::FlashLoanCpiNotAllowed);
+
+        let mut index = usize::from(current_index) + 1;
+        while let Ok(instruction) = load_instruction_at_checked(index, &instructions) {
+            // `RepayFlashLoan` 的第二个账户为池子
+            if instruction.program_id == crate::ID
+                && instruction.data.starts_with(crate::instruction::RepayFlashLoan::DISCRIMINATOR)
+                && instruction.accounts.get(1).map(|meta| meta.pubkey) == Some(self.swap.key())
+            {
+                return Ok(());
+            }
+            index += 1;
+        }
+
+        err!(SwapError::FlashLoanRepayMissing)
+    }
+}
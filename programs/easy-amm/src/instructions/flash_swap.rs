@@ -0,0 +1,319 @@
+//! 闪电兑换
+//! 池子先转出 `amount_out`，再 CPI 调用调用方指定的程序，回调结束后按回调后的金库余额
+//! 校验扣除手续费后的不变量，回调中需把源代币转入金库
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{instruction::Instruction, program::invoke},
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use spl_math::uint::U256;
+
+use crate::{
+    error::SwapError, 
+    events::FlashSwapEvent, 
+    shared::{
+        calculation_fee, 
+        interest_scale, 
+        mint_owner_fee, 
+        PoolInvariant, 
+        scale_interest_amount, 
+        to_u64, 
+        transfer_tokens
+    }, 
+    state::{Observations, Swap}
+};
+
+
+#[derive(Accounts)]
+pub struct FlashSwap<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_SWAP) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        address = swap.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        address = swap.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
+        token::authority = swap
+    )]
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::POOL_MINT_SEEDS
+        ],
+        bump = swap.pool_mint_bump_seed,
+        mint::authority = swap,
+        mint::token_program = lp_token_program
+    )]
+    pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
+    #[account(
+        mut,
+        address = swap.pool_fee_account,
+        token::mint = swap.pool_mint,
+        token::token_program = lp_token_program
+    )]
+    pub pool_fee_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_a_mint
+    )]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_b_mint
+    )]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: 回调程序，由调用方指定，remaining accounts 原样传入
+    #[account(
+        executable
+    )]
+    pub callback_program: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+
+impl<'info> FlashSwap<'info> {
+    pub fn process(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        a_to_b: bool,
+        amount_out: u64,
+        data: Vec<u8>
+    ) -> Result<()> {
+        // 以兑换前的储备更新 TWAP 累计价格
+        self.swap.update_price_oracle(&self.observations)?;
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
+        let (reserve_a, reserve_b) = (self.swap.reserve_a, self.swap.reserve_b);
+        // 转入数量按金库余额变化计算，与储备无关
+        let (vault_a, vault_b) = (self.token_a.amount, self.token_b.amount);
+        let (amount_out_a, amount_out_b) = if a_to_b {
+            (0, amount_out)
+        } else {
+            (amount_out, 0)
+        };
+        require!(
+            amount_out > 0 && amount_out_a < reserve_a && amount_out_b < reserve_b,
+            SwapError::ZeroTradingTokens
+        );
+
+        // 回调期间锁定池子，先写回账户数据，防止回调重入其他指令
+        self.swap.flash_loan_locked = true;
+        self.swap.exit(&crate::ID)?;
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            Swap::SWAP_SEEDS,
+            self.swap.token_a_mint.as_ref(),
+            self.swap.token_b_mint.as_ref(),
+            &[self.swap.swap_bump_seed]
+        ]];
+        if a_to_b {
+            transfer_tokens(
+                &self.token_b, 
+                &self.user_token_b, 
+                amount_out, 
+                &self.token_b_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+        } else {
+            transfer_tokens(
+                &self.token_a, 
+                &self.user_token_a, 
+                amount_out, 
+                &self.token_a_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+        }
+        msg!("闪电兑换转出: {}", amount_out);
+
+        // 回调，池子不为回调签名
+        let mut account_infos = remaining_accounts.to_vec();
+        account_infos.push(self.callback_program.to_account_info());
+        invoke(
+            &Instruction {
+                program_id: self.callback_program.key(),
+                accounts: remaining_accounts
+                    .iter()
+                    .map(|account| AccountMeta {
+                        pubkey: account.key(),
+                        is_signer: account.is_signer,
+                        is_writable: account.is_writable,
+                    })
+                    .collect(),
+                data,
+            },
+            &account_infos
+        )?;
+
+        // 回调结束时 Anchor 会写回内存中的 swap，先重新加载，不覆盖回调期间账户上的数据
+        self.swap.reload()?;
+        require!(self.swap.flash_loan_locked, SwapError::PoolLocked);
+        self.token_a.reload()?;
+        self.token_b.reload()?;
+        let amount_in_a = self.token_a.amount.saturating_sub(
+            vault_a.checked_sub(amount_out_a).ok_or(SwapError::CalculationFailure)?
+        );
+        let amount_in_b = self.token_b.amount.saturating_sub(
+            vault_b.checked_sub(amount_out_b).ok_or(SwapError::CalculationFailure)?
+        );
+        require!(amount_in_a > 0 || amount_in_b > 0, SwapError::FlashSwapUnderpaid);
+
+        self.swap.withdraw_reserves(amount_out_a, amount_out_b)?;
+        self.swap.deposit_reserves(amount_in_a, amount_in_b)?;
+        self.check_invariant(reserve_a, reserve_b, amount_in_a, amount_in_b)?;
+        self.swap.flash_loan_locked = false;
+        msg!("闪电兑换转入: a {}, b {}", amount_in_a, amount_in_b);
+
+        // 两侧转入各自按 `owner_trade_fees` 计协议手续费，以池币形式铸造给 pool_fee_account
+        let owner_fee_pool_tokens_a = self.mint_owner_fee(true, amount_in_a)?;
+        self.pool_mint.reload()?;
+        let owner_fee_pool_tokens_b = self.mint_owner_fee(false, amount_in_b)?;
+
+        emit!(FlashSwapEvent {
+            swap: self.swap.key(),
+            user: self.user.key(),
+            callback_program: self.callback_program.key(),
+            a_to_b,
+            amount_out,
+            amount_in_a,
+            amount_in_b,
+            owner_fee_pool_tokens: owner_fee_pool_tokens_a
+                .checked_add(owner_fee_pool_tokens_b)
+                .ok_or(SwapError::CalculationFailure)?,
+        });
+
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
+    }
+
+    /// 按转入数量计算协议手续费并铸造池币，`is_a` 表示转入的是 token_a，返回铸造的池币数量
+    fn mint_owner_fee(&self, is_a: bool, amount_in: u64) -> Result<u64> {
+        let owner_fee = to_u64(calculation_fee(
+            u128::from(amount_in), 
+            u128::from(self.swap.owner_trade_fees)
+        ).ok_or(SwapError::FeeCalculationFailure)?)?;
+        let (source_mint, destination_mint) = if is_a {
+            (&self.token_a_mint, &self.token_b_mint)
+        } else {
+            (&self.token_b_mint, &self.token_a_mint)
+        };
+        let (reserve_source, reserve_destination) = self.swap.reserves(is_a);
+
+        let (owner_fee_pool_tokens, _) = mint_owner_fee(
+            &self.swap, 
+            &self.pool_mint, 
+            &self.pool_fee_account, 
+            None, 
+            &self.lp_token_program, 
+            owner_fee, 
+            reserve_source, 
+            reserve_destination, 
+            interest_scale(source_mint)?, 
+            interest_scale(destination_mint)?
+        )?;
+        Ok(owner_fee_pool_tokens)
+    }
+
+    /// 从兑换后的储备中扣除转入部分的手续费(向上取整)，要求不变量不小于兑换前
+    fn check_invariant(
+        &self,
+        reserve_a: u64,
+        reserve_b: u64,
+        amount_in_a: u64,
+        amount_in_b: u64
+    ) -> Result<()> {
+        let fees = self.swap.total_trade_fees();
+        let adjusted = |balance: u64, amount_in: u64| -> Option<u128> {
+            let fee = (u128::from(amount_in) * fees).div_ceil(u128::from(Swap::FEES_BASIS_POINTS));
+            u128::from(balance).checked_sub(fee)
+        };
+        let balance_a = adjusted(self.swap.reserve_a, amount_in_a)
+            .ok_or(SwapError::FlashSwapUnderpaid)?;
+        let balance_b = adjusted(self.swap.reserve_b, amount_in_b)
+            .ok_or(SwapError::FlashSwapUnderpaid)?;
+
+        let holds = if self.swap.curve_type == Swap::CURVE_CONSTANT_PRODUCT {
+            U256::from(balance_a) * U256::from(balance_b)
+                >= U256::from(reserve_a) * U256::from(reserve_b)
+        } else {
+            // 同一笔交易内比例不变，恒定乘积无需换算；StableSwap 按含息数量比较
+            let scale_a = interest_scale(&self.token_a_mint)?;
+            let scale_b = interest_scale(&self.token_b_mint)?;
+            let curve = self.swap.curve()?;
+            let before = curve.normalized_value(
+                scale_interest_amount(reserve_a, scale_a, false)?, 
+                scale_interest_amount(reserve_b, scale_b, false)?
+            ).ok_or(SwapError::CalculationFailure)?;
+            let after = curve.normalized_value(
+                scale_interest_amount(to_u64(balance_a)?, scale_a, false)?, 
+                scale_interest_amount(to_u64(balance_b)?, scale_b, false)?
+            ).ok_or(SwapError::CalculationFailure)?;
+            after >= before
+        };
+        require!(holds, SwapError::FlashSwapUnderpaid);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+//! 扩大观测账户的槽位数
+
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+
+use crate::{
+    error::SwapError,
+    state::{Observations, Swap}
+};
+
+
+#[derive(Accounts)]
+pub struct IncreaseObservationCardinality<'info> {
+    /// 支付扩容所需的租金
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+impl<'info> IncreaseObservationCardinality<'info> {
+    /// 账户扩容到 `cardinality_next` 个槽位，新槽位在当前环写满后才启用，
+    /// 保证环内观测点始终按时间有序。单次扩容受运行时 10 KiB 的上限约束
+    pub fn process(&mut self, cardinality_next: u16) -> Result<()> {
+        require!(
+            cardinality_next <= Observations::MAX_CARDINALITY,
+            SwapError::InvalidObservationCardinality
+        );
+
+        let old_cardinality_next = self.observations.load()?.cardinality_next;
+        if cardinality_next <= old_cardinality_next {
+            msg!("观测槽位数: {} -> {}", old_cardinality_next, old_cardinality_next);
+            return Ok(());
+        }
+
+        let info = self.observations.to_account_info();
+        let space = Observations::space(cardinality_next);
+        let lamports = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+        if lamports > 0 {
+            transfer(
+                CpiContext::new(
+                    self.system_program.to_account_info(),
+                    Transfer {
+                        from: self.payer.to_account_info(),
+                        to: info.clone(),
+                    }
+                ),
+                lamports
+            )?;
+        }
+        info.realloc(space, true)?;
+
+        self.observations.load_mut()?.cardinality_next = cardinality_next;
+        msg!("观测槽位数: {} -> {}", old_cardinality_next, cardinality_next);
+
+        Ok(())
+    }
+}
@@ -1,19 +1,67 @@
 //! 池子初始化
 
 use anchor_lang::prelude::*;
+use anchor_lang::{
+    solana_program::program_pack::Pack,
+    system_program::{allocate, assign, create_account, transfer, Allocate, Assign, CreateAccount, Transfer},
+};
 use anchor_spl::{
-    associated_token::AssociatedToken, 
-    token_interface::{Mint, TokenAccount, TokenInterface}
+    associated_token::{create, AssociatedToken, Create}, 
+    token_2022::{
+        spl_token_2022::{
+            extension::{
+                confidential_transfer::ConfidentialTransferMint, 
+                default_account_state::DefaultAccountState, 
+                non_transferable::NonTransferable, 
+                permanent_delegate::PermanentDelegate, 
+                BaseStateWithExtensions, 
+                ExtensionType, 
+                StateWithExtensions
+            },
+            state::{AccountState, Mint as Mint_2022},
+        },
+        ID as TOKEN_2022_PROGRAM_ID,
+    },
+    token_2022_extensions::{
+        metadata_pointer_initialize, 
+        spl_pod::optional_keys::OptionalNonZeroPubkey, 
+        spl_token_metadata_interface::state::TokenMetadata, 
+        token_metadata_initialize, 
+        MetadataPointerInitialize, 
+        TokenMetadataInitialize
+    },
+    token_interface::{
+        initialize_mint2, 
+        mint_to_checked, 
+        InitializeMint2, 
+        Mint, 
+        MintToChecked, 
+        TokenAccount, 
+        TokenInterface
+    }
 };
 
-use crate::{error::SwapError, events::InitializeSwapEvent, shared::{mint_tokens, transfer_tokens}, state::Swap};
+use crate::{error::SwapError, events::InitializeSwapEvent, shared::{amount_after_transfer_fee, transfer_tokens}, state::{Observation, Observations, Swap}};
+
 
 
+/// LP 元数据中 `uri` 的长度上限，与 Metaplex 元数据一致
+pub const MAX_LP_URI_LEN: usize = 200;
+/// 每一侧代币在 LP 名称与符号中最多占用的字符数
+pub const MAX_LP_SYMBOL_PART_LEN: usize = 10;
+/// 代币没有元数据符号时用 mint 地址的前几个字符代替
+pub const SHORT_MINT_KEY_LEN: usize = 4;
+
 
 /// Initializes a new swap pool.
 ///
 /// ⚠️ `user` and `payer` **must not be the same account**.
 /// This is to prevent conflicts during token transfers and fee accounting.
+///
+/// `payer` becomes the pool admin and owns `pool_fees_account`.
+///
+/// The swap PDA is keyed by its mint pair, so `token_a_mint` must sort
+/// before `token_b_mint`; this keeps exactly one pool per pair.
 #[derive(Accounts)]
 pub struct InitializeSwap<'info> {
     #[account(mut)]
@@ -28,7 +76,9 @@ pub struct InitializeSwap<'info> {
     pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(
         constraint = token_b_mint.key() != token_a_mint.key() 
-            @ SwapError::DuplicateMint
+            @ SwapError::DuplicateMint,
+        constraint = token_a_mint.key() < token_b_mint.key()
+            @ SwapError::InvalidMintOrder
     )]
     pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
 
@@ -50,7 +100,11 @@ pub struct InitializeSwap<'info> {
         init,
         payer = payer,
         space = Swap::SWAP_SPACE,
-        seeds = [Swap::SWAP_SEEDS],
+        seeds = [
+            Swap::SWAP_SEEDS,
+            token_a_mint.key().as_ref(),
+            token_b_mint.key().as_ref()
+        ],
         bump
     )]
     pub swap: Box<Account<'info, Swap>>,
@@ -81,54 +135,113 @@ pub struct InitializeSwap<'info> {
     )]
     pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    /// CHECK: LP mint 在 process 中创建，Token-2022 下可带元数据扩展
     #[account(
-        init,
-        payer = payer,
+        mut,
         seeds = [
             swap.key().as_ref(),
             Swap::POOL_MINT_SEEDS
         ],
-        bump,
-        mint::authority = swap,
-        mint::decimals = 6
+        bump
     )]
-    pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub pool_mint: UncheckedAccount<'info>,
 
+    /// CHECK: payer 的 LP ATA，需在 LP mint 之后创建
     #[account(
-        init,
-        payer = payer,
-        associated_token::mint = pool_mint,
-        associated_token::authority = payer
+        mut,
+        seeds = [
+            payer.key().as_ref(),
+            lp_token_program.key().as_ref(),
+            pool_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub pool_fees_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub pool_fees_account: UncheckedAccount<'info>,
+
+    /// CHECK: user 的 LP ATA，需在 LP mint 之后创建
+    #[account(
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            lp_token_program.key().as_ref(),
+            pool_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
+    )]
+    pub destination: UncheckedAccount<'info>,
+
+    /// CHECK: 池子 PDA 的 LP ATA，存放永久锁定的 `MINIMUM_LIQUIDITY`，程序不会从中转出
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            lp_token_program.key().as_ref(),
+            pool_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
+    )]
+    pub locked_pool_token_account: UncheckedAccount<'info>,
 
     #[account(
         init,
         payer = payer,
-        associated_token::mint = pool_mint,
-        associated_token::authority = user
+        space = Observations::space(1),
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump
     )]
-    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub observations: AccountLoader<'info, Observations>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>
 }
 
 
 impl<'info> InitializeSwap<'info> {
+    #[allow(clippy::too_many_arguments)]
     pub fn process(
-        &mut self, 
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         trade_fees: u16, 
         withdraw_fees: u16,
+        owner_trade_fees: u16,
+        host_fees: u16,
+        flash_fees: u16,
         amount_a: u64,
         amount_b: u64,
+        curve_type: u8,
+        amp_factor: u64,
+        lp_decimals: u8,
+        lp_metadata_uri: Option<String>,
         bumps: InitializeSwapBumps
     ) -> Result<()> {
-        require!(trade_fees <= Swap::MAX_TRADE_FEES, SwapError::TradeFeeTooHigh);
-        require!(withdraw_fees <= Swap::MAX_WITHDRAW_FEES, SwapError::WithdrawFeeTooHigh);
-        require_gt!(amount_a, 0, SwapError::ZeroInitialLiquidity);
-        require_gt!(amount_b, 0, SwapError::ZeroInitialLiquidity);
+        Swap::validate_fees(trade_fees, withdraw_fees, owner_trade_fees, host_fees, flash_fees)?;
+        Self::validate_mint(&self.token_a_mint)?;
+        Self::validate_mint(&self.token_b_mint)?;
+        let amp_factor = match curve_type {
+            Swap::CURVE_CONSTANT_PRODUCT => 0,
+            Swap::CURVE_STABLE => {
+                require!(
+                    (Swap::MIN_AMP_FACTOR..=Swap::MAX_AMP_FACTOR).contains(&amp_factor),
+                    SwapError::InvalidAmpFactor
+                );
+                amp_factor
+            },
+            _ => return err!(SwapError::InvalidCurveType),
+        };
+        // 以金库扣除转账手续费后实际收到的数量作为初始流动性
+        let initial_a = amount_after_transfer_fee(amount_a, &self.token_a_mint)?;
+        let initial_b = amount_after_transfer_fee(amount_b, &self.token_b_mint)?;
+        require_gt!(initial_a, 0, SwapError::ZeroInitialLiquidity);
+        require_gt!(initial_b, 0, SwapError::ZeroInitialLiquidity);
 
         // 充值
         msg!("token_a 首充 {}", amount_a);
@@ -139,7 +252,8 @@ impl<'info> InitializeSwap<'info> {
             &self.token_a_mint, 
             self.user.to_account_info(), 
             &self.token_program,
-            None
+            None, 
+            remaining_accounts
         )?;
 
         msg!("token_b 首充 {}", amount_b);
@@ -150,21 +264,36 @@ impl<'info> InitializeSwap<'info> {
             &self.token_b_mint, 
             self.user.to_account_info(), 
             &self.token_program,
-            None
+            None, 
+            remaining_accounts
         )?;
 
-        // 铸造代币
+        let token_a_mint_key = self.token_a_mint.key();
+        let token_b_mint_key = self.token_b_mint.key();
+        let swap_seeds: &[&[&[u8]]] = &[&[
+            Swap::SWAP_SEEDS,
+            token_a_mint_key.as_ref(),
+            token_b_mint_key.as_ref(),
+            &[bumps.swap]
+        ]];
+        self.create_pool_mint(lp_decimals, lp_metadata_uri, bumps.pool_mint, swap_seeds)?;
+        self.create_pool_token_account(self.pool_fees_account.to_account_info(), self.payer.to_account_info())?;
+        self.create_pool_token_account(self.destination.to_account_info(), self.user.to_account_info())?;
+        self.create_pool_token_account(self.locked_pool_token_account.to_account_info(), self.swap.to_account_info())?;
+
+        // 铸造代币，其中 MINIMUM_LIQUIDITY 永久锁定
         msg!("铸造代币 {}", Swap::INITIAL_SWAP_POOL_AMOUNT);
-        mint_tokens(
-            &self.pool_mint, 
-            &self.destination, 
-            Swap::INITIAL_SWAP_POOL_AMOUNT, 
-            self.swap.to_account_info(), 
-            &self.token_program, 
-            &[&[
-                Swap::SWAP_SEEDS,
-                &[bumps.swap]
-            ]]
+        self.mint_pool_tokens(
+            self.locked_pool_token_account.to_account_info(), 
+            Swap::MINIMUM_LIQUIDITY, 
+            lp_decimals, 
+            swap_seeds
+        )?;
+        self.mint_pool_tokens(
+            self.destination.to_account_info(), 
+            Swap::INITIAL_SWAP_POOL_AMOUNT - Swap::MINIMUM_LIQUIDITY, 
+            lp_decimals, 
+            swap_seeds
         )?;
 
         self.swap.set_inner(Swap { 
@@ -174,13 +303,33 @@ impl<'info> InitializeSwap<'info> {
             pool_mint: self.pool_mint.key(), 
             token_a_mint: self.token_a_mint.key(), 
             token_b_mint: self.token_b_mint.key(), 
+            admin: self.payer.key(),
             trade_fees, 
             withdraw_fees, 
             swap_bump_seed: bumps.swap, 
             pool_mint_bump_seed: bumps.pool_mint, 
             token_a_bump_seed: bumps.token_a, 
-            token_b_bump_seed: bumps.token_b 
+            token_b_bump_seed: bumps.token_b,
+            paused: 0,
+            curve_type,
+            amp_factor,
+            owner_trade_fees,
+            host_fees,
+            price_a_cumulative_last: 0,
+            price_b_cumulative_last: 0,
+            block_timestamp_last: Clock::get()?.unix_timestamp,
+            flash_fees,
+            flash_loan_locked: false,
+            flash_loan_active: false,
+            flash_loan_reserve_a: 0,
+            flash_loan_reserve_b: 0,
+            flash_loan_fee_a: 0,
+            flash_loan_fee_b: 0,
+            reserve_a: initial_a,
+            reserve_b: initial_b,
         });
+        self.initialize_observations(bumps.observations)?;
+
         // ------------------------------------------------------------------
         // Emit off‑chain event so indexers / front‑end can track pool creation
         // ------------------------------------------------------------------
@@ -190,10 +339,262 @@ impl<'info> InitializeSwap<'info> {
             token_a: self.token_a.key(),
             token_b: self.token_b.key(),
             pool_mint: self.pool_mint.key(),
-            initial_a: amount_a,
-            initial_b: amount_b,
+            initial_a,
+            initial_b,
             lp_issued: Swap::INITIAL_SWAP_POOL_AMOUNT,
         });
         Ok(())
     }
+
+    /// 观测账户从 1 个槽位开始，以池子创建时刻作为第一个观测点
+    fn initialize_observations(&self, bump: u8) -> Result<()> {
+        let (mut header, mut slots) = Observations::load_init(&self.observations)?;
+        header.swap = self.swap.key();
+        header.bump = bump;
+        header.index = 0;
+        header.cardinality = 1;
+        header.cardinality_next = 1;
+        slots[0] = Observation {
+            block_timestamp: self.swap.block_timestamp_last,
+            ..Default::default()
+        };
+        msg!("创建观测账户: {}", self.observations.key());
+
+        Ok(())
+    }
+
+    /// 拒绝会破坏池子假设的 mint：可冻结金库、可被永久代理划走、不可转账、
+    /// 新账户默认冻结，或余额加密无法读取
+    fn validate_mint(mint: &InterfaceAccount<'info, Mint>) -> Result<()> {
+        require!(mint.freeze_authority.is_none(), SwapError::MintHasFreezeAuthority);
+
+        let mint_info = mint.to_account_info();
+        if mint_info.owner != &TOKEN_2022_PROGRAM_ID {
+            return Ok(());
+        }
+        let mint_data = mint_info.try_borrow_data()?;
+        let data = StateWithExtensions::<Mint_2022>::unpack(&mint_data)?;
+
+        require!(
+            data.get_extension::<PermanentDelegate>().is_err(),
+            SwapError::MintHasPermanentDelegate
+        );
+        require!(
+            data.get_extension::<NonTransferable>().is_err(),
+            SwapError::MintNonTransferable
+        );
+        if let Ok(default_state) = data.get_extension::<DefaultAccountState>() {
+            require!(
+                default_state.state != AccountState::Frozen as u8,
+                SwapError::MintDefaultAccountStateFrozen
+            );
+        }
+        require!(
+            data.get_extension::<ConfidentialTransferMint>().is_err(),
+            SwapError::MintHasConfidentialTransfer
+        );
+
+        Ok(())
+    }
+
+    /// 代币的元数据符号(Token-2022 `TokenMetadata` 扩展)，截取前 `MAX_LP_SYMBOL_PART_LEN` 个字符；
+    /// 没有元数据或符号为空时取 mint 地址的前 `SHORT_MINT_KEY_LEN` 个字符
+    fn mint_symbol(mint: &InterfaceAccount<'info, Mint>) -> Result<String> {
+        let mint_info = mint.to_account_info();
+        if mint_info.owner == &TOKEN_2022_PROGRAM_ID {
+            let mint_data = mint_info.try_borrow_data()?;
+            let data = StateWithExtensions::<Mint_2022>::unpack(&mint_data)?;
+            if let Ok(metadata) = data.get_variable_len_extension::<TokenMetadata>() {
+                let symbol: String = metadata.symbol
+                    .trim()
+                    .chars()
+                    .take(MAX_LP_SYMBOL_PART_LEN)
+                    .collect();
+                if !symbol.is_empty() {
+                    return Ok(symbol);
+                }
+            }
+        }
+        Ok(mint.key().to_string().chars().take(SHORT_MINT_KEY_LEN).collect())
+    }
+
+    /// 创建 LP mint。传入 `lp_metadata_uri` 时要求 LP 使用 Token-2022，并通过 MetadataPointer
+    /// 把元数据写在 mint 自身；名称与符号由两侧代币的符号生成，调用方只能指定 `uri`
+    fn create_pool_mint(
+        &self,
+        lp_decimals: u8,
+        lp_metadata_uri: Option<String>,
+        pool_mint_bump: u8,
+        swap_seeds: &[&[&[u8]]]
+    ) -> Result<()> {
+        let metadata = match lp_metadata_uri {
+            Some(uri) => {
+                require_keys_eq!(
+                    self.lp_token_program.key(), 
+                    TOKEN_2022_PROGRAM_ID, 
+                    SwapError::LpMetadataRequiresToken2022
+                );
+                require!(uri.len() <= MAX_LP_URI_LEN, SwapError::InvalidLpMetadata);
+                let symbol_a = Self::mint_symbol(&self.token_a_mint)?;
+                let symbol_b = Self::mint_symbol(&self.token_b_mint)?;
+                Some(TokenMetadata {
+                    update_authority: OptionalNonZeroPubkey::try_from(Some(self.swap.key()))?,
+                    mint: self.pool_mint.key(),
+                    name: format!("easy-amm {}-{} LP", symbol_a, symbol_b),
+                    symbol: format!("{}-{}-LP", symbol_a, symbol_b),
+                    uri,
+                    additional_metadata: vec![],
+                })
+            },
+            None => None,
+        };
+
+        // 元数据由 token 程序写入时自动扩容，租金需提前付足
+        let space = match &metadata {
+            Some(_) => ExtensionType::try_calculate_account_len::<Mint_2022>(&[ExtensionType::MetadataPointer])?,
+            None => Mint_2022::LEN,
+        };
+        let metadata_space = match &metadata {
+            Some(metadata) => metadata.tlv_size_of()?,
+            None => 0,
+        };
+        let lamports = Rent::get()?.minimum_balance(space + metadata_space);
+
+        let swap_key = self.swap.key();
+        let pool_mint_seeds: &[&[&[u8]]] = &[&[swap_key.as_ref(), Swap::POOL_MINT_SEEDS, &[pool_mint_bump]]];
+        let current_lamports = self.pool_mint.lamports();
+        if current_lamports == 0 {
+            create_account(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(), 
+                    CreateAccount {
+                        from: self.payer.to_account_info(),
+                        to: self.pool_mint.to_account_info(),
+                    },
+                    pool_mint_seeds
+                ), 
+                lamports, 
+                space as u64, 
+                self.lp_token_program.key
+            )?;
+        } else {
+            // 与 Anchor `init` 一致：地址已被预先转入 lamports 时 create_account 会失败，
+            // 改为补足租金后分配空间并转移所有权，防止他人预充值阻止该币对建池
+            let required_lamports = lamports.saturating_sub(current_lamports);
+            if required_lamports > 0 {
+                transfer(
+                    CpiContext::new(
+                        self.system_program.to_account_info(), 
+                        Transfer {
+                            from: self.payer.to_account_info(),
+                            to: self.pool_mint.to_account_info(),
+                        }
+                    ), 
+                    required_lamports
+                )?;
+            }
+            allocate(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(), 
+                    Allocate { account_to_allocate: self.pool_mint.to_account_info() },
+                    pool_mint_seeds
+                ), 
+                space as u64
+            )?;
+            assign(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(), 
+                    Assign { account_to_assign: self.pool_mint.to_account_info() },
+                    pool_mint_seeds
+                ), 
+                self.lp_token_program.key
+            )?;
+        }
+
+        if metadata.is_some() {
+            metadata_pointer_initialize(
+                CpiContext::new(
+                    self.lp_token_program.to_account_info(), 
+                    MetadataPointerInitialize {
+                        token_program_id: self.lp_token_program.to_account_info(),
+                        mint: self.pool_mint.to_account_info(),
+                    }
+                ), 
+                Some(self.swap.key()), 
+                Some(self.pool_mint.key())
+            )?;
+        }
+
+        initialize_mint2(
+            CpiContext::new(
+                self.lp_token_program.to_account_info(), 
+                InitializeMint2 { mint: self.pool_mint.to_account_info() }
+            ), 
+            lp_decimals, 
+            &self.swap.key(), 
+            None
+        )?;
+
+        if let Some(metadata) = metadata {
+            msg!("LP 元数据: {} {}", metadata.name, metadata.symbol);
+            token_metadata_initialize(
+                CpiContext::new_with_signer(
+                    self.lp_token_program.to_account_info(), 
+                    TokenMetadataInitialize {
+                        program_id: self.lp_token_program.to_account_info(),
+                        metadata: self.pool_mint.to_account_info(),
+                        update_authority: self.swap.to_account_info(),
+                        mint_authority: self.swap.to_account_info(),
+                        mint: self.pool_mint.to_account_info(),
+                    },
+                    swap_seeds
+                ), 
+                metadata.name, 
+                metadata.symbol, 
+                metadata.uri
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn create_pool_token_account(
+        &self,
+        associated_token: AccountInfo<'info>,
+        authority: AccountInfo<'info>
+    ) -> Result<()> {
+        create(CpiContext::new(
+            self.associated_token_program.to_account_info(), 
+            Create {
+                payer: self.payer.to_account_info(),
+                associated_token,
+                authority,
+                mint: self.pool_mint.to_account_info(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.lp_token_program.to_account_info(),
+            }
+        ))
+    }
+
+    fn mint_pool_tokens(
+        &self,
+        to: AccountInfo<'info>,
+        amount: u64,
+        lp_decimals: u8,
+        swap_seeds: &[&[&[u8]]]
+    ) -> Result<()> {
+        mint_to_checked(
+            CpiContext::new_with_signer(
+                self.lp_token_program.to_account_info(), 
+                MintToChecked { 
+                    mint: self.pool_mint.to_account_info(), 
+                    to, 
+                    authority: self.swap.to_account_info()
+                },
+                swap_seeds
+            ), 
+            amount, 
+            lp_decimals
+        )
+    }
 }
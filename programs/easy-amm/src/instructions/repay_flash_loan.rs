@@ -0,0 +1,154 @@
+//! 归还闪电贷
+//! 补足借出前的金库余额与闪电贷手续费，手续费留在池子中归 LP 所有
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{
+    error::SwapError, 
+    shared::{amount_before_transfer_fee, PoolInvariant, transfer_tokens}, 
+    state::Swap
+};
+
+
+#[derive(Accounts)]
+pub struct RepayFlashLoan<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = swap.flash_loan_active @ SwapError::FlashLoanNotActive
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        address = swap.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        address = swap.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
+        token::authority = swap
+    )]
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            swap.key().as_ref(),
+            Swap::POOL_MINT_SEEDS
+        ],
+        bump = swap.pool_mint_bump_seed,
+        mint::authority = swap
+    )]
+    pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        token::mint = token_a_mint,
+        token::authority = user
+    )]
+    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_b_mint,
+        token::authority = user
+    )]
+    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+
+impl<'info> RepayFlashLoan<'info> {
+    pub fn process(&mut self, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
+        let required_a = self.swap.flash_loan_reserve_a
+            .checked_add(self.swap.flash_loan_fee_a)
+            .ok_or(SwapError::CalculationFailure)?;
+        let required_b = self.swap.flash_loan_reserve_b
+            .checked_add(self.swap.flash_loan_fee_b)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        let due_a = required_a.saturating_sub(self.token_a.amount);
+        if due_a > 0 {
+            let amount = amount_before_transfer_fee(due_a, &self.token_a_mint)?;
+            transfer_tokens(
+                &self.user_token_a, 
+                &self.token_a, 
+                amount, 
+                &self.token_a_mint, 
+                self.user.to_account_info(), 
+                &self.token_program, 
+                None, 
+                remaining_accounts
+            )?;
+            msg!("归还闪电贷 token_a: {}", amount);
+        }
+
+        let due_b = required_b.saturating_sub(self.token_b.amount);
+        if due_b > 0 {
+            let amount = amount_before_transfer_fee(due_b, &self.token_b_mint)?;
+            transfer_tokens(
+                &self.user_token_b, 
+                &self.token_b, 
+                amount, 
+                &self.token_b_mint, 
+                self.user.to_account_info(), 
+                &self.token_program, 
+                None, 
+                remaining_accounts
+            )?;
+            msg!("归还闪电贷 token_b: {}", amount);
+        }
+
+        // 以实际到账余额为准
+        self.token_a.reload()?;
+        self.token_b.reload()?;
+        require!(
+            self.token_a.amount >= required_a && self.token_b.amount >= required_b,
+            SwapError::FlashLoanNotRepaid
+        );
+
+        // 闪电贷手续费归 LP，计入储备
+        let (fee_a, fee_b) = (self.swap.flash_loan_fee_a, self.swap.flash_loan_fee_b);
+        self.swap.deposit_reserves(fee_a, fee_b)?;
+
+        self.swap.flash_loan_locked = false;
+        self.swap.flash_loan_active = false;
+        self.swap.flash_loan_reserve_a = 0;
+        self.swap.flash_loan_reserve_b = 0;
+        self.swap.flash_loan_fee_a = 0;
+        self.swap.flash_loan_fee_b = 0;
+
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
+    }
+}
@@ -0,0 +1,307 @@
+//! 多跳路由兑换
+//! 按 remaining accounts 中的池子顺序依次兑换，中间代币直接在池子金库之间转移，
+//! 只对最终输出做一次滑点检查
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{
+    error::SwapError, 
+    events::{RouteSwapEvent, SwapEvent}, 
+    shared::{
+        amount_after_transfer_fee, 
+        amount_before_transfer_fee, 
+        calculation_fee, 
+        check_deadline, 
+        interest_scale, 
+        mint_owner_fee, 
+        PoolInvariant, 
+        scale_interest_amount, 
+        to_u64, 
+        transfer_tokens, 
+        unscale_interest_amount
+    }, 
+    state::{Observations, Swap}
+};
+
+
+/// 每一跳在 remaining accounts 中占用的账户数
+pub const ROUTE_HOP_ACCOUNTS: usize = 8;
+pub const MAX_ROUTE_HOPS: usize = 4;
+
+
+#[derive(Accounts)]
+pub struct RouteSwap<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        token::authority = user
+    )]
+    pub user_source_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user_destination_token: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
+}
+
+
+/// 单跳账户，顺序为：池子、源金库、目标金库、源代币 mint、目标代币 mint、池币 mint、协议手续费账户、观测账户
+struct RouteHop<'info> {
+    swap: Account<'info, Swap>,
+    pool_source_token: InterfaceAccount<'info, TokenAccount>,
+    pool_destination_token: InterfaceAccount<'info, TokenAccount>,
+    source_mint: InterfaceAccount<'info, Mint>,
+    destination_mint: InterfaceAccount<'info, Mint>,
+    pool_mint: InterfaceAccount<'info, Mint>,
+    pool_fee_account: InterfaceAccount<'info, TokenAccount>,
+    observations: AccountLoader<'info, Observations>,
+}
+
+impl<'info> RouteHop<'info> {
+    fn load(accounts: &'info [AccountInfo<'info>]) -> Result<Self> {
+        let swap = Account::<Swap>::try_from(&accounts[0])?;
+        require!(!swap.is_paused(Swap::PAUSE_SWAP), SwapError::PoolPaused);
+        require!(!swap.flash_loan_locked, SwapError::PoolLocked);
+
+        let pool_source_token = InterfaceAccount::<TokenAccount>::try_from(&accounts[1])?;
+        let pool_destination_token = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+        let valid_vaults = (pool_source_token.key() == swap.token_a 
+                && pool_destination_token.key() == swap.token_b)
+            || (pool_source_token.key() == swap.token_b 
+                && pool_destination_token.key() == swap.token_a);
+        require!(valid_vaults, SwapError::InvalidRoute);
+
+        let source_mint = InterfaceAccount::<Mint>::try_from(&accounts[3])?;
+        let destination_mint = InterfaceAccount::<Mint>::try_from(&accounts[4])?;
+        require_keys_eq!(source_mint.key(), pool_source_token.mint, SwapError::InvalidRoute);
+        require_keys_eq!(destination_mint.key(), pool_destination_token.mint, SwapError::InvalidRoute);
+
+        let pool_mint = InterfaceAccount::<Mint>::try_from(&accounts[5])?;
+        let pool_fee_account = InterfaceAccount::<TokenAccount>::try_from(&accounts[6])?;
+        require_keys_eq!(pool_mint.key(), swap.pool_mint, SwapError::InvalidRoute);
+        require_keys_eq!(pool_fee_account.key(), swap.pool_fee_account, SwapError::InvalidRoute);
+
+        let observations = AccountLoader::<Observations>::try_from(&accounts[7])?;
+        require_keys_eq!(observations.load()?.swap, swap.key(), SwapError::InvalidRoute);
+
+        Ok(Self {
+            swap,
+            pool_source_token,
+            pool_destination_token,
+            source_mint,
+            destination_mint,
+            pool_mint,
+            pool_fee_account,
+            observations,
+        })
+    }
+}
+
+
+impl<'info> RouteSwap<'info> {
+    pub fn process(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+        hops: u8,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        require_gt!(amount_in, 0, SwapError::ZeroTradingTokens);
+        let hop_accounts_len = usize::from(hops) * ROUTE_HOP_ACCOUNTS;
+        require!(
+            hops > 0
+                && usize::from(hops) <= MAX_ROUTE_HOPS
+                && remaining_accounts.len() >= hop_accounts_len,
+            SwapError::InvalidRoute
+        );
+        // 各跳账户之后为转账钩子所需的账户，转账时原样转发
+        let (hop_accounts, hook_accounts) = remaining_accounts.split_at(hop_accounts_len);
+
+        // 所有余额在任何转账之前读取，因此同一个池子不能出现两次
+        let mut hops = hop_accounts
+            .chunks(ROUTE_HOP_ACCOUNTS)
+            .map(RouteHop::load)
+            .collect::<Result<Vec<_>>>()?;
+        self.check_route(&hops)?;
+
+        // 第一跳池子实际收到的数量
+        let mut amount_received = amount_after_transfer_fee(amount_in, &hops[0].source_mint)?;
+        let mut source_transfer_amount = amount_in;
+        let mut input_token = self.user_source_token.key();
+
+        for index in 0..hops.len() {
+            let (hop, rest) = hops[index..].split_first_mut().ok_or(SwapError::InvalidRoute)?;
+
+            // 以兑换前的储备更新 TWAP 累计价格
+            let a_to_b = hop.pool_source_token.key() == hop.swap.token_a;
+            hop.swap.update_price_oracle(&hop.observations)?;
+            let (reserve_source, reserve_destination) = hop.swap.reserves(a_to_b);
+            let invariant = if a_to_b {
+                PoolInvariant::load(&hop.swap, &hop.pool_mint, &hop.source_mint, &hop.destination_mint)?
+            } else {
+                PoolInvariant::load(&hop.swap, &hop.pool_mint, &hop.destination_mint, &hop.source_mint)?
+            };
+
+            let curve = hop.swap.curve()?;
+            let source_scale = interest_scale(&hop.source_mint)?;
+            let destination_scale = interest_scale(&hop.destination_mint)?;
+            let (source_amount_swapped, destination_amount_swapped) = curve.swap(
+                hop.swap.total_trade_fees(), 
+                scale_interest_amount(amount_received, source_scale, false)?, 
+                scale_interest_amount(reserve_source, source_scale, false)?, 
+                scale_interest_amount(reserve_destination, destination_scale, false)?
+            ).ok_or(SwapError::ZeroTradingTokens)?;
+            let source_amount_swapped = unscale_interest_amount(source_amount_swapped, source_scale, true)?;
+            let destination_amount_swapped = unscale_interest_amount(destination_amount_swapped, destination_scale, false)?;
+
+            // 第一跳金库正好收到 source_amount_swapped，之后各跳收到上一跳转入的全部数量
+            let reserve_in = if index == 0 { source_amount_swapped } else { amount_received };
+            if a_to_b {
+                hop.swap.deposit_reserves(reserve_in, 0)?;
+                hop.swap.withdraw_reserves(0, destination_amount_swapped)?;
+            } else {
+                hop.swap.deposit_reserves(0, reserve_in)?;
+                hop.swap.withdraw_reserves(destination_amount_swapped, 0)?;
+            }
+
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                Swap::SWAP_SEEDS,
+                hop.swap.token_a_mint.as_ref(),
+                hop.swap.token_b_mint.as_ref(),
+                &[hop.swap.swap_bump_seed]
+            ]];
+
+            // 第一跳由用户支付，之后的输入已由上一跳转入
+            if index == 0 {
+                source_transfer_amount = amount_before_transfer_fee(
+                    source_amount_swapped, 
+                    &hop.source_mint
+                )?;
+                // 换算回原始数量与反推转账手续费都向上取整，结果不能超过用户给出的 amount_in
+                require!(source_transfer_amount <= amount_in, SwapError::ExceededSlippage);
+                transfer_tokens(
+                    &self.user_source_token, 
+                    &hop.pool_source_token, 
+                    source_transfer_amount, 
+                    &hop.source_mint, 
+                    self.user.to_account_info(), 
+                    &self.token_program, 
+                    None, 
+                    hook_accounts
+                )?;
+                msg!("路由兑换(用户转账): {}", source_transfer_amount);
+            }
+
+            // 最后一跳转给用户，其余转入下一跳的源金库
+            let destination_token = match rest.first() {
+                Some(next) => &next.pool_source_token,
+                None => &self.user_destination_token,
+            };
+            transfer_tokens(
+                &hop.pool_destination_token, 
+                destination_token, 
+                destination_amount_swapped, 
+                &hop.destination_mint, 
+                hop.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                hook_accounts
+            )?;
+            msg!("路由兑换(第 {} 跳池子转账): {}", index + 1, destination_amount_swapped);
+
+            // 协议手续费(源代币计)以池币形式铸造给 pool_fee_account
+            let owner_fee = to_u64(calculation_fee(
+                u128::from(source_amount_swapped), 
+                u128::from(hop.swap.owner_trade_fees)
+            ).ok_or(SwapError::FeeCalculationFailure)?)?;
+            let (reserve_source, reserve_destination) = hop.swap.reserves(a_to_b);
+            let (owner_fee_pool_tokens, _) = mint_owner_fee(
+                &hop.swap, 
+                &hop.pool_mint, 
+                &hop.pool_fee_account, 
+                None, 
+                &self.lp_token_program, 
+                owner_fee, 
+                reserve_source, 
+                reserve_destination, 
+                source_scale, 
+                destination_scale
+            )?;
+
+            emit!(SwapEvent {
+                user: self.user.key(),
+                user_source_token: input_token,
+                user_destination_token: destination_token.key(),
+                pool_source_token: hop.pool_source_token.key(),
+                pool_destination_token: hop.pool_destination_token.key(),
+                from_mint: hop.source_mint.key(),
+                to_mint: hop.destination_mint.key(),
+                amount_in: if index == 0 { source_transfer_amount } else { amount_received },
+                amount_out: destination_amount_swapped,
+                owner_fee,
+                owner_fee_pool_tokens,
+                host_fee_pool_tokens: 0,
+            });
+
+            let (token_a, token_b) = if a_to_b {
+                (&mut hop.pool_source_token, &mut hop.pool_destination_token)
+            } else {
+                (&mut hop.pool_destination_token, &mut hop.pool_source_token)
+            };
+            invariant.check(&hop.swap, token_a, token_b, &mut hop.pool_mint)?;
+            hop.swap.exit(&crate::ID)?;
+            input_token = hop.pool_destination_token.key();
+            amount_received = amount_after_transfer_fee(destination_amount_swapped, &hop.destination_mint)?;
+        }
+
+        if amount_received < minimum_amount_out {
+            return err!(SwapError::ExceededSlippage);
+        }
+
+        emit!(RouteSwapEvent {
+            user: self.user.key(),
+            from_mint: self.user_source_token.mint,
+            to_mint: self.user_destination_token.mint,
+            hops: hops.len() as u8,
+            amount_in: source_transfer_amount,
+            amount_out: amount_received,
+        });
+
+        Ok(())
+    }
+
+    /// 相邻两跳的代币需要首尾相接，每个池子只能出现一次，且 LP mint 属于 `lp_token_program`
+    fn check_route(&self, hops: &[RouteHop<'info>]) -> Result<()> {
+        let (first, last) = match (hops.first(), hops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return err!(SwapError::InvalidRoute),
+        };
+        require_keys_eq!(first.source_mint.key(), self.user_source_token.mint, SwapError::InvalidRoute);
+        require_keys_eq!(last.destination_mint.key(), self.user_destination_token.mint, SwapError::InvalidRoute);
+
+        for (index, hop) in hops.iter().enumerate() {
+            if let Some(next) = hops.get(index + 1) {
+                require_keys_eq!(hop.destination_mint.key(), next.source_mint.key(), SwapError::InvalidRoute);
+            }
+            require!(
+                hops[..index].iter().all(|other| other.swap.key() != hop.swap.key()),
+                SwapError::InvalidRoute
+            );
+            require_keys_eq!(
+                *hop.pool_mint.to_account_info().owner, 
+                self.lp_token_program.key(), 
+                SwapError::InvalidRoute
+            );
+        }
+
+        Ok(())
+    }
+}
+
@@ -0,0 +1,47 @@
+//! 转移池子管理员(仅管理员)
+
+use anchor_lang::prelude::*;
+
+use crate::{error::SwapError, events::AdminUpdatedEvent, state::Swap};
+
+
+#[derive(Accounts)]
+pub struct SetAdmin<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        has_one = admin @ SwapError::Unauthorized,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+}
+
+
+impl<'info> SetAdmin<'info> {
+    /// `new_admin` 不能为空地址，也不能与当前管理员相同
+    pub fn process(&mut self, new_admin: Pubkey) -> Result<()> {
+        require!(
+            new_admin != Pubkey::default() && new_admin != self.swap.admin,
+            SwapError::InvalidAdmin
+        );
+
+        let old_admin = self.swap.admin;
+        self.swap.admin = new_admin;
+        msg!("转移管理员: {} -> {}", old_admin, new_admin);
+
+        emit!(AdminUpdatedEvent {
+            swap: self.swap.key(),
+            old_admin,
+            admin: new_admin,
+        });
+
+        Ok(())
+    }
+}
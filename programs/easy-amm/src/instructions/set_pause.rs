@@ -0,0 +1,45 @@
+//! 暂停 / 恢复池子操作(仅管理员)
+
+use anchor_lang::prelude::*;
+
+use crate::{error::SwapError, events::PauseUpdatedEvent, state::Swap};
+
+
+#[derive(Accounts)]
+pub struct SetPause<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        has_one = admin @ SwapError::Unauthorized,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+}
+
+
+impl<'info> SetPause<'info> {
+    /// `paused` 为 `Swap::PAUSE_*` 的组合，传 0 表示全部恢复
+    pub fn process(&mut self, paused: u8) -> Result<()> {
+        require!(paused & !Swap::PAUSE_ALL == 0, SwapError::InvalidPauseFlags);
+
+        let old_paused = self.swap.paused;
+        self.swap.paused = paused;
+        msg!("更新暂停状态: {:#05b} -> {:#05b}", old_paused, paused);
+
+        emit!(PauseUpdatedEvent {
+            swap: self.swap.key(),
+            admin: self.admin.key(),
+            old_paused,
+            paused,
+        });
+
+        Ok(())
+    }
+}
@@ -1,23 +1,44 @@
 //! 公用函数
-//! 转账、铸币、计算手续费、池币兑换
+//! 转账、铸币、计算手续费、池币兑换、定价曲线
 
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, system_program};
+use anchor_spl::associated_token::{create, AssociatedToken, Create};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::{
+    spl_token_2022::{
+        extension::{
+            interest_bearing_mint::InterestBearingConfig, 
+            transfer_fee::TransferFeeConfig, 
+            BaseStateWithExtensions, 
+            StateWithExtensions
+        },
+        native_mint::ID as NATIVE_MINT_2022,
+        onchain::invoke_transfer_checked,
+        state::Mint as Mint_2022,
+    },
+    ID as TOKEN_2022_PROGRAM_ID,
+};
 use anchor_spl::token_interface::{
     Mint, 
     TokenAccount, 
     TokenInterface,
-    transfer_checked,
-    TransferChecked,
     mint_to_checked,
     MintToChecked,
     BurnChecked,
-    burn_checked
+    burn_checked,
+    CloseAccount,
+    close_account,
+    SyncNative,
+    sync_native
 };
-use spl_math::{checked_ceil_div::CheckedCeilDiv, precise_number::PreciseNumber};
+use spl_math::{approximations::sqrt, checked_ceil_div::CheckedCeilDiv, precise_number::PreciseNumber, uint::U256};
 
-use crate::{error::SwapError, state::Swap};
+use crate::{error::SwapError, state::{Observation, Observations, Swap}};
 
 
+/// 转账，`remaining_accounts` 中需包含 TransferHook 扩展要求的额外账户
+/// (按 `ExtraAccountMetaList` 解析)，没有转账钩子的 mint 会忽略这些账户
+#[allow(clippy::too_many_arguments)]
 pub fn transfer_tokens<'info>(
     from: &InterfaceAccount<'info, TokenAccount>,
     to: &InterfaceAccount<'info, TokenAccount>,
@@ -25,32 +46,104 @@ pub fn transfer_tokens<'info>(
     mint: &InterfaceAccount<'info, Mint>,
     authority: AccountInfo<'info>,
     token_program: &Interface<'info, TokenInterface>,
-    signer_seeds: Option<&[&[&[u8]]]>
+    signer_seeds: Option<&[&[&[u8]]]>,
+    remaining_accounts: &[AccountInfo<'info>]
 ) -> Result<()> {
-    let cpi = match signer_seeds {
-        Some(seeds) => CpiContext::new_with_signer(
-            token_program.to_account_info(), 
-            TransferChecked {
-                from: from.to_account_info(), 
-                mint: mint.to_account_info(), 
-                to: to.to_account_info(), 
-                authority: authority,
-            }, 
-            seeds
-        ),
-        None => CpiContext::new(
-            token_program.to_account_info(),
-            TransferChecked {
-                from: from.to_account_info(), 
-                mint: mint.to_account_info(), 
-                to: to.to_account_info(), 
-                authority: authority
-            } 
-        )
+    transfer_token_infos(
+        from.to_account_info(), 
+        to.to_account_info(), 
+        amount, 
+        mint, 
+        authority, 
+        token_program, 
+        signer_seeds, 
+        remaining_accounts
+    )
+}
+
+
+/// 同 `transfer_tokens`，用于一侧是 `UserTokenAccount` 的转账
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_token_infos<'info>(
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    amount: u64,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+    signer_seeds: Option<&[&[&[u8]]]>,
+    remaining_accounts: &[AccountInfo<'info>]
+) -> Result<()> {
+    invoke_transfer_checked(
+        token_program.key, 
+        from, 
+        mint.to_account_info(), 
+        to, 
+        authority, 
+        remaining_accounts, 
+        amount, 
+        mint.decimals, 
+        signer_seeds.unwrap_or_default()
+    ).map_err(Into::into)
+}
 
-    };
 
-    transfer_checked(cpi, amount, mint.decimals)
+/// 用户在某个 mint 下的 ATA，不存在时由本指令创建。
+/// 记录账户是否为本指令新建，原生 SOL 解包只关闭本指令新建的 wSOL 账户
+pub struct UserTokenAccount<'info> {
+    info: AccountInfo<'info>,
+    mint: Pubkey,
+    created: bool,
+}
+
+impl<'info> UserTokenAccount<'info> {
+    /// `info` 的地址已由账户约束校验为 `user` 在 `mint` 下的 ATA
+    pub fn load_or_create(
+        info: AccountInfo<'info>,
+        user: &Signer<'info>,
+        mint: &InterfaceAccount<'info, Mint>,
+        token_program: &Interface<'info, TokenInterface>,
+        system_program: &Program<'info, System>,
+        associated_token_program: &Program<'info, AssociatedToken>
+    ) -> Result<Self> {
+        let created = info.data_is_empty();
+        if created {
+            create(CpiContext::new(
+                associated_token_program.to_account_info(), 
+                Create {
+                    payer: user.to_account_info(),
+                    associated_token: info.clone(),
+                    authority: user.to_account_info(),
+                    mint: mint.to_account_info(),
+                    system_program: system_program.to_account_info(),
+                    token_program: token_program.to_account_info(),
+                }
+            ))?;
+        }
+        require_keys_eq!(*info.owner, token_program.key(), ErrorCode::ConstraintTokenTokenProgram);
+
+        let user_token = Self { info, mint: mint.key(), created };
+        user_token.amount()?;
+        Ok(user_token)
+    }
+
+    /// 当前余额
+    pub fn amount(&self) -> Result<u64> {
+        let data = self.info.try_borrow_data()?;
+        Ok(TokenAccount::try_deserialize(&mut &data[..])?.amount)
+    }
+}
+
+impl<'info> AsRef<AccountInfo<'info>> for UserTokenAccount<'info> {
+    fn as_ref(&self) -> &AccountInfo<'info> {
+        &self.info
+    }
+}
+
+impl Key for UserTokenAccount<'_> {
+    fn key(&self) -> Pubkey {
+        *self.info.key
+    }
 }
 
 
@@ -69,7 +162,7 @@ pub fn mint_tokens<'info>(
             MintToChecked { 
                 mint: mint.to_account_info(), 
                 to: destination.to_account_info(), 
-                authority: authority
+                authority
             },
             signer_seeds
         ), 
@@ -93,7 +186,7 @@ pub fn burn_tokens<'info>(
             BurnChecked { 
                 mint: mint.to_account_info(), 
                 from: from.to_account_info(), 
-                authority: authority 
+                authority 
             }
         ), 
         amount, 
@@ -102,6 +195,322 @@ pub fn burn_tokens<'info>(
 }
 
 
+/// 协议手续费已留在池子中，按单币存入折算成池币铸造给 `pool_fee_account`，
+/// 传入推荐方账户时按 `host_fees` 分出一部分，返回 (协议池币总量, 推荐方池币)。
+/// `swap_source_amount` / `swap_destination_amount` 为兑换后的储备
+#[allow(clippy::too_many_arguments)]
+pub fn mint_owner_fee<'info>(
+    swap: &Account<'info, Swap>,
+    pool_mint: &InterfaceAccount<'info, Mint>,
+    pool_fee_account: &InterfaceAccount<'info, TokenAccount>,
+    host_fee_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    token_program: &Interface<'info, TokenInterface>,
+    owner_fee: u64,
+    swap_source_amount: u64,
+    swap_destination_amount: u64,
+    source_scale: u128,
+    destination_scale: u128,
+) -> Result<(u64, u64)> {
+    if owner_fee == 0 {
+        return Ok((0, 0));
+    }
+
+    let pool_token_amount = swap.curve()?.deposit_single_token_type(
+        0,
+        scale_interest_amount(owner_fee, source_scale, false)?,
+        scale_interest_amount(
+            swap_source_amount
+                .checked_sub(owner_fee)
+                .ok_or(SwapError::CalculationFailure)?,
+            source_scale,
+            false
+        )?,
+        scale_interest_amount(swap_destination_amount, destination_scale, false)?,
+        u128::from(pool_mint.supply)
+    ).ok_or(SwapError::FeeCalculationFailure)?;
+    let pool_token_amount = to_u64(pool_token_amount)?;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        Swap::SWAP_SEEDS,
+        swap.token_a_mint.as_ref(),
+        swap.token_b_mint.as_ref(),
+        &[swap.swap_bump_seed]
+    ]];
+
+    let host_fee = match host_fee_account {
+        Some(host_fee_account) => {
+            let host_fee = to_u64(calculation_fee(
+                u128::from(pool_token_amount), 
+                u128::from(swap.host_fees)
+            ).ok_or(SwapError::FeeCalculationFailure)?)?;
+
+            if host_fee > 0 {
+                mint_tokens(
+                    pool_mint, 
+                    host_fee_account, 
+                    host_fee, 
+                    swap.to_account_info(), 
+                    token_program, 
+                    signer_seeds
+                )?;
+                msg!("推荐方手续费(池币): {}", host_fee);
+            }
+            host_fee
+        },
+        None => 0,
+    };
+
+    let owner_pool_token_amount = pool_token_amount
+        .checked_sub(host_fee)
+        .ok_or(SwapError::FeeCalculationFailure)?;
+    if owner_pool_token_amount > 0 {
+        mint_tokens(
+            pool_mint, 
+            pool_fee_account, 
+            owner_pool_token_amount, 
+            swap.to_account_info(), 
+            token_program, 
+            signer_seeds
+        )?;
+        msg!("协议手续费(池币): {}", owner_pool_token_amount);
+    }
+
+    Ok((pool_token_amount, host_fee))
+}
+
+
+/// 是否为原生 SOL 的包装 mint(SPL Token 或 Token-2022)
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::ID || *mint == NATIVE_MINT_2022
+}
+
+
+/// 原生 SOL 自动包装：用户 wSOL 账户余额不足 `amount` 时，从用户转入差额 lamports 并 `sync_native`。
+/// 返回包装后的可用余额，非 wSOL 账户直接返回当前余额
+pub fn wrap_native<'info>(
+    user: &Signer<'info>,
+    token_account: &UserTokenAccount<'info>,
+    amount: u64,
+    token_program: &Interface<'info, TokenInterface>,
+    system_program: &Program<'info, System>
+) -> Result<u64> {
+    let balance = token_account.amount()?;
+    if !is_native_mint(&token_account.mint) || balance >= amount {
+        return Ok(balance);
+    }
+
+    let lamports = amount - balance;
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(), 
+            system_program::Transfer {
+                from: user.to_account_info(),
+                to: token_account.to_account_info(),
+            }
+        ), 
+        lamports
+    )?;
+    sync_native(CpiContext::new(
+        token_program.to_account_info(), 
+        SyncNative { account: token_account.to_account_info() }
+    ))?;
+    msg!("包装 SOL: {}", lamports);
+
+    Ok(amount)
+}
+
+
+/// 原生 SOL 自动解包：关闭本指令新建的 wSOL 账户，剩余 lamports(含租金)全部退回用户。
+/// 用户事先已有的账户与非 wSOL 账户不处理
+pub fn unwrap_native<'info>(
+    user: &Signer<'info>,
+    token_account: &UserTokenAccount<'info>,
+    token_program: &Interface<'info, TokenInterface>
+) -> Result<()> {
+    if !token_account.created || !is_native_mint(&token_account.mint) {
+        return Ok(());
+    }
+
+    close_account(CpiContext::new(
+        token_program.to_account_info(), 
+        CloseAccount {
+            account: token_account.to_account_info(),
+            destination: user.to_account_info(),
+            authority: user.to_account_info(),
+        }
+    ))?;
+    msg!("解包 SOL");
+
+    Ok(())
+}
+
+
+/// 转出 `amount` 后对方实际收到的数量(扣除 Token-2022 转账手续费)
+pub fn amount_after_transfer_fee(amount: u64, mint: &InterfaceAccount<Mint>) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if mint_info.owner != &TOKEN_2022_PROGRAM_ID {
+        return Ok(amount);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let data = StateWithExtensions::<Mint_2022>::unpack(&mint_data)?;
+    if let Ok(transfer_fee_config) = data.get_extension::<TransferFeeConfig>() {
+        let fee = transfer_fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        amount.checked_sub(fee).ok_or(error!(SwapError::FeeCalculationFailure))
+    } else {
+        Ok(amount)
+    }
+}
+
+
+/// 需要转出的数量，使扣除 Token-2022 转账手续费后对方正好收到 `amount`
+pub fn amount_before_transfer_fee(amount: u64, mint: &InterfaceAccount<Mint>) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if mint_info.owner != &TOKEN_2022_PROGRAM_ID {
+        return Ok(amount);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let data = StateWithExtensions::<Mint_2022>::unpack(&mint_data)?;
+    if let Ok(transfer_fee_config) = data.get_extension::<TransferFeeConfig>() {
+        let fee = transfer_fee_config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(SwapError::FeeCalculationFailure)?;
+        amount.checked_add(fee).ok_or(error!(SwapError::FeeCalculationFailure))
+    } else {
+        Ok(amount)
+    }
+}
+
+
+/// 计息缩放比例的精度，无 `InterestBearingConfig` 扩展的 mint 比例即为该值
+pub const INTEREST_SCALE_ONE: u128 = 1_000_000_000_000;
+
+
+/// mint 当前的计息缩放比例(原始数量 -> 含息数量)，精度为 `INTEREST_SCALE_ONE`
+pub fn interest_scale(mint: &InterfaceAccount<Mint>) -> Result<u128> {
+    let mint_info = mint.to_account_info();
+    if mint_info.owner != &TOKEN_2022_PROGRAM_ID {
+        return Ok(INTEREST_SCALE_ONE);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let data = StateWithExtensions::<Mint_2022>::unpack(&mint_data)?;
+    if let Ok(config) = data.get_extension::<InterestBearingConfig>() {
+        let scale = compound_interest_scale(
+            i64::from(config.initialization_timestamp), 
+            i16::from(config.pre_update_average_rate), 
+            i64::from(config.last_update_timestamp), 
+            i16::from(config.current_rate), 
+            Clock::get()?.unix_timestamp
+        ).ok_or(SwapError::CalculationFailure)?;
+        require_gt!(scale, 0, SwapError::CalculationFailure);
+        Ok(scale)
+    } else {
+        Ok(INTEREST_SCALE_ONE)
+    }
+}
+
+
+/// 与 Token-2022 一致，一年按 365.24 天计
+const SECONDS_PER_YEAR: i128 = 31_556_736;
+/// `fixed_exp` 内部精度，比 `INTEREST_SCALE_ONE` 多 6 位，换算时四舍五入
+const EXP_ONE: u128 = 1_000_000_000_000_000_000;
+/// e，精度为 `EXP_ONE`
+const EXP_E: u128 = 2_718_281_828_459_045_235;
+/// 指数 x 的上限，超过后比例不再变化。e^16 ≈ 8.9e6，任意 u64 数量换算后仍在 u128 内；
+/// 按最高利率 327.67% 约 4.9 年达到，避免高利率 mint 让计算溢出后池子永久不可用
+const MAX_INTEREST_EXPONENT: u128 = 16;
+
+
+/// 按 InterestBearingConfig 的连续复利计算 e^x，结果精度为 `INTEREST_SCALE_ONE`。
+/// x = (pre_update_average_rate·(last_update - init) + current_rate·(now - last_update))
+///     / (SECONDS_PER_YEAR·10_000)，利率单位为基点，|x| 超过 `MAX_INTEREST_EXPONENT` 时取上限
+fn compound_interest_scale(
+    initialization_timestamp: i64,
+    pre_update_average_rate: i16,
+    last_update_timestamp: i64,
+    current_rate: i16,
+    now: i64,
+) -> Option<u128> {
+    let pre_update = i128::from(pre_update_average_rate)
+        .checked_mul(i128::from(last_update_timestamp.checked_sub(initialization_timestamp)?))?;
+    let post_update = i128::from(current_rate)
+        .checked_mul(i128::from(now.checked_sub(last_update_timestamp)?))?;
+    let numerator = pre_update.checked_add(post_update)?;
+    let denominator = U256::from(SECONDS_PER_YEAR * 10_000);
+
+    let one = U256::from(EXP_ONE);
+    let exponent = U256::from(numerator.unsigned_abs())
+        .checked_mul(one)?
+        .checked_div(denominator)?
+        .min(U256::from(MAX_INTEREST_EXPONENT).checked_mul(one)?);
+    let exp = fixed_exp(exponent)?;
+    // e^-x = 1 / e^x
+    let scale = if numerator >= 0 {
+        exp
+    } else {
+        one.checked_mul(one)?.checked_div(exp)?
+    };
+
+    let factor = U256::from(EXP_ONE / INTEREST_SCALE_ONE);
+    let scale = scale.checked_add(factor / 2)?.checked_div(factor)?;
+    u128::try_from(scale).ok()
+}
+
+
+/// e^x，x 与结果的精度均为 `EXP_ONE`。
+/// 整数部分逐次乘以 e，小数部分用泰勒级数，逐项累加到项小于精度为止
+fn fixed_exp(x: U256) -> Option<U256> {
+    let one = U256::from(EXP_ONE);
+    let integer = x.checked_div(one)?;
+    let fraction = x.checked_rem(one)?;
+
+    let mut sum = one;
+    let mut term = one;
+    let mut n = 1u64;
+    while !term.is_zero() {
+        term = term.checked_mul(fraction)?.checked_div(one)?.checked_div(U256::from(n))?;
+        sum = sum.checked_add(term)?;
+        n += 1;
+    }
+
+    // 结果超过 U256 之前 checked_mul 即返回 None，整数部分不会很大
+    if integer > U256::from(u8::MAX) {
+        return None;
+    }
+    let e = U256::from(EXP_E);
+    for _ in 0..integer.as_u64() {
+        sum = sum.checked_mul(e)?.checked_div(one)?;
+    }
+    Some(sum)
+}
+
+
+/// 原始数量换算成含息数量，定价曲线统一使用含息数量
+pub fn scale_interest_amount(amount: u64, scale: u128, ceiling: bool) -> Result<u128> {
+    mul_div(u128::from(amount), scale, INTEREST_SCALE_ONE, ceiling)
+        .ok_or(error!(SwapError::CalculationFailure))
+}
+
+
+/// 含息数量换算回原始数量
+pub fn unscale_interest_amount(amount: u128, scale: u128, ceiling: bool) -> Result<u64> {
+    to_u64(
+        mul_div(amount, INTEREST_SCALE_ONE, scale, ceiling)
+            .ok_or(SwapError::CalculationFailure)?
+    )
+}
+
+
+fn mul_div(amount: u128, numerator: u128, denominator: u128, ceiling: bool) -> Option<u128> {
+    let product = amount.checked_mul(numerator)?;
+    if ceiling {
+        product.checked_add(denominator.checked_sub(1)?)?.checked_div(denominator)
+    } else {
+        product.checked_div(denominator)
+    }
+}
+
+
 /// 计算反向交易所需的输入数量，即为了获得指定的输出，需要多少输入
 pub fn pre_trading_fee_amoun(amounts: u128, fee_amount: u128) -> Option<u128> {
     if fee_amount == 0 {
@@ -136,6 +545,49 @@ pub fn calculation_fee(amounts: u128, fee_amount: u128) -> Option<u128> {
 
 
 
+/// 单币存入 / 精确输出的单币提取时“数量的一半”视为兑换，按交易手续费反推(向上取整)后返回含手续费的数量
+pub fn gross_up_half_trade_fee(source_amount: u128, trade_fee_amount: u128) -> Option<u128> {
+    let half_source_amount = source_amount.checked_add(1)?.checked_div(2)?;
+    let trade_fee_source_amount = pre_trading_fee_amoun(
+        half_source_amount, 
+        trade_fee_amount
+    )?;
+
+    source_amount
+        .checked_sub(half_source_amount)?
+        .checked_add(trade_fee_source_amount)
+}
+
+
+/// 单币提取时“提取数量的一半”视为兑换，为 `gross_up_half_trade_fee` 的反函数：
+/// 到账数量 = 提取数量 · (2 - 2f) / (2 - f)，向下取整
+pub fn deduct_half_trade_fee(source_amount: u128, trade_fee_amount: u128) -> Option<u128> {
+    if trade_fee_amount == 0 {
+        return Some(source_amount);
+    }
+
+    let fee_denominator = u128::from(Swap::FEES_BASIS_POINTS);
+    let numerator = fee_denominator.checked_sub(trade_fee_amount)?.checked_mul(2)?;
+    let denominator = fee_denominator.checked_mul(2)?.checked_sub(trade_fee_amount)?;
+
+    source_amount
+        .checked_mul(numerator)?
+        .checked_div(denominator)
+}
+
+
+/// 校验交易截止时间(unix 时间戳)，未传入时不检查
+pub fn check_deadline(deadline: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        require!(
+            Clock::get()?.unix_timestamp <= deadline,
+            SwapError::DeadlineExceeded
+        );
+    }
+    Ok(())
+}
+
+
 pub fn to_u64(val: u128) -> Result<u64> {
     val.try_into().map_err(|_| error!(SwapError::ConversionFailure))
 }
@@ -187,16 +639,7 @@ pub fn withdraw_single_token_type_exact_out(
 ) -> Option<u128> {
     // 由于我们希望计算出为了获得精确的输出，需要多少池子代币，
     // 因此我们需要获取“源代币数量的一半”兑换为另一种代币时所产生的反向交易手续费
-    let half_source_amount = source_amount.checked_add(1)?.checked_div(2)?;
-
-    let trade_fee_source_amount = pre_trading_fee_amoun(
-        half_source_amount, 
-        trade_fee_amount
-    )?;
-
-    let source_amount = source_amount
-        .checked_sub(half_source_amount)?
-        .checked_add(trade_fee_source_amount)?;
+    let source_amount = gross_up_half_trade_fee(source_amount, trade_fee_amount)?;
 
     let swap_source_amount = PreciseNumber::new(swap_token_amount)?;
     let source_amount = PreciseNumber::new(source_amount)?;
@@ -214,6 +657,29 @@ pub fn withdraw_single_token_type_exact_out(
 }
 
 
+/// 根据销毁的池子代币数量，计算可以提取的 token A 或 B 数量(向下取整)，
+/// 为 `withdraw_single_token_type_exact_out` 的反向计算，“提取数量的一半”按兑换收取交易手续费
+pub fn withdraw_single_token_type_exact_in(
+    trade_fee_amount: u128,
+    pool_tokens: u128,
+    swap_token_amount: u128,
+    pool_supply: u128,
+) -> Option<u128> {
+    let pool_tokens = PreciseNumber::new(pool_tokens)?;
+    let pool_supply = PreciseNumber::new(pool_supply)?;
+    let one = PreciseNumber::new(1)?;
+    let base = one.checked_sub(&pool_tokens.checked_div(&pool_supply)?)?;
+    let ratio = one.checked_sub(&base.checked_mul(&base)?)?;
+
+    let source_amount = PreciseNumber::new(swap_token_amount)?
+        .checked_mul(&ratio)?
+        .floor()?
+        .to_imprecise()?;
+
+    deduct_half_trade_fee(source_amount, trade_fee_amount)
+}
+
+
 /// 根据存入的 token A 或 B 数量，计算可以获得的池子代币数量
 pub fn deposit_single_token_type(
     trade_fee_amount: u128,
@@ -239,6 +705,29 @@ pub fn deposit_single_token_type(
 }
 
 
+/// 根据期望获得的池子代币数量，计算需要存入的 token A 或 B 数量(向上取整)，
+/// 为 `deposit_single_token_type` 的反向计算，“存入数量的一半”按兑换收取交易手续费
+pub fn deposit_single_token_type_exact_out(
+    trade_fee_amount: u128,
+    pool_tokens: u128,
+    swap_token_amount: u128,
+    pool_supply: u128,
+) -> Option<u128> {
+    let pool_tokens = PreciseNumber::new(pool_tokens)?;
+    let pool_supply = PreciseNumber::new(pool_supply)?;
+    let one = PreciseNumber::new(1)?;
+    let base = one.checked_add(&pool_tokens.checked_div(&pool_supply)?)?;
+    let ratio = base.checked_mul(&base)?.checked_sub(&one)?;
+
+    let source_amount = PreciseNumber::new(swap_token_amount)?
+        .checked_mul(&ratio)?
+        .ceiling()?
+        .to_imprecise()?;
+
+    gross_up_half_trade_fee(source_amount, trade_fee_amount)
+}
+
+
 /// 计算能兑换到的代币
 pub fn calculate_exchange_amount(
     trade_fee_amount: u128,
@@ -261,4 +750,1047 @@ pub fn calculate_exchange_amount(
     let source_amount_swapped = source_amount_swapped.checked_add(trade_fee)?;
     
     Some((source_amount_swapped, destination_amount_swapped))
-}
\ No newline at end of file
+}
+
+
+/// 根据期望得到的目标代币数量，反向计算需要投入的源代币数量(含交易手续费)
+pub fn calculate_exchange_amount_exact_out(
+    trade_fee_amount: u128,
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<(u128, u128)> {
+    let invariant = swap_source_amount.checked_mul(swap_destination_amount)?;
+    let new_swap_destination_amount = swap_destination_amount.checked_sub(destination_amount)?;
+    if new_swap_destination_amount == 0 {
+        return None;
+    }
+
+    // 向上取整，保证兑换后 x·y 不减少
+    let new_swap_source_amount = invariant
+        .checked_add(new_swap_destination_amount)?
+        .checked_sub(1)?
+        .checked_div(new_swap_destination_amount)?;
+    let source_amount_less_fee = new_swap_source_amount.checked_sub(swap_source_amount)?;
+
+    let source_amount_swapped = pre_trading_fee_amoun(source_amount_less_fee, trade_fee_amount)?;
+
+    Some((source_amount_swapped, destination_amount))
+}
+
+
+// ----------------------------------------------------------------------
+// StableSwap 曲线(两币种)
+//
+// 不变量: A·n^n·Σx + D = A·D·n^n + D^(n+1) / (n^n·Πx)
+// 与 SPL token-swap 一致，池子上保存的 amp_factor 实际为 A·n^(n-1)
+// ----------------------------------------------------------------------
+
+const N_COINS: u128 = 2;
+const STABLE_ITERATIONS: u8 = 32;
+
+
+/// 牛顿迭代计算不变量 D
+pub fn compute_d(amp_factor: u64, amount_a: u128, amount_b: u128) -> Option<u128> {
+    let sum_x = amount_a.checked_add(amount_b)?;
+    if sum_x == 0 {
+        return Some(0);
+    }
+
+    let ann = U256::from(u128::from(amp_factor).checked_mul(N_COINS)?);
+    let amount_a_times_coins = U256::from(amount_a.checked_mul(N_COINS)?);
+    let amount_b_times_coins = U256::from(amount_b.checked_mul(N_COINS)?);
+    let sum_x = U256::from(sum_x);
+
+    let mut d = sum_x;
+    for _ in 0..STABLE_ITERATIONS {
+        let d_prod = d
+            .checked_mul(d)?
+            .checked_div(amount_a_times_coins)?
+            .checked_mul(d)?
+            .checked_div(amount_b_times_coins)?;
+        let d_prev = d;
+
+        // d = (Ann·S + D_P·n)·d / ((Ann - 1)·d + (n + 1)·D_P)
+        let numerator = ann
+            .checked_mul(sum_x)?
+            .checked_add(d_prod.checked_mul(U256::from(N_COINS))?)?
+            .checked_mul(d)?;
+        let denominator = ann
+            .checked_sub(U256::one())?
+            .checked_mul(d)?
+            .checked_add(d_prod.checked_mul(U256::from(N_COINS + 1))?)?;
+        d = numerator.checked_div(denominator)?;
+
+        let diff = if d > d_prev { d - d_prev } else { d_prev - d };
+        if diff <= U256::one() {
+            break;
+        }
+    }
+
+    u128::try_from(d).ok()
+}
+
+
+/// 已知一侧新余额和不变量 D，计算另一侧余额(向上取整)
+pub fn compute_new_destination_amount(
+    amp_factor: u64,
+    new_source_amount: u128,
+    d_val: u128,
+) -> Option<u128> {
+    let ann = U256::from(u128::from(amp_factor).checked_mul(N_COINS)?);
+    let new_source_amount = U256::from(new_source_amount);
+    let d_val = U256::from(d_val);
+
+    // c = D^(n+1) / (n^n·x'·Ann)
+    let c = d_val
+        .checked_pow(U256::from(N_COINS + 1))?
+        .checked_div(
+            new_source_amount
+                .checked_mul(U256::from(N_COINS * N_COINS))?
+                .checked_mul(ann)?
+        )?;
+    // b = x' + D / Ann
+    let b = new_source_amount.checked_add(d_val.checked_div(ann)?)?;
+
+    // y = (y^2 + c) / (2y + b - D)
+    let mut y = d_val;
+    for _ in 0..STABLE_ITERATIONS {
+        let numerator = y.checked_mul(y)?.checked_add(c)?;
+        let denominator = y
+            .checked_mul(U256::from(2u8))?
+            .checked_add(b)?
+            .checked_sub(d_val)?;
+        if denominator.is_zero() {
+            return None;
+        }
+        let mut y_new = numerator.checked_div(denominator)?;
+        if !numerator.checked_rem(denominator)?.is_zero() {
+            y_new = y_new.checked_add(U256::one())?;
+        }
+
+        if y_new == y {
+            break;
+        }
+        y = y_new;
+    }
+
+    u128::try_from(y).ok()
+}
+
+
+/// StableSwap 曲线下计算能兑换到的代币
+pub fn stable_calculate_exchange_amount(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<(u128, u128)> {
+    let trade_fee = calculation_fee(source_amount, trade_fee_amount)?;
+    let source_amount_less_fess = source_amount.checked_sub(trade_fee)?;
+
+    let d = compute_d(amp_factor, swap_source_amount, swap_destination_amount)?;
+    let new_swap_source_amount = swap_source_amount.checked_add(source_amount_less_fess)?;
+    let new_swap_destination_amount = compute_new_destination_amount(
+        amp_factor, 
+        new_swap_source_amount, 
+        d
+    )?;
+
+    let destination_amount_swapped = swap_destination_amount
+        .checked_sub(new_swap_destination_amount)?;
+
+    Some((source_amount, destination_amount_swapped))
+}
+
+
+/// StableSwap 曲线下根据期望得到的目标代币数量，反向计算需要投入的源代币数量
+pub fn stable_calculate_exchange_amount_exact_out(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    destination_amount: u128,
+    swap_source_amount: u128,
+    swap_destination_amount: u128,
+) -> Option<(u128, u128)> {
+    let new_swap_destination_amount = swap_destination_amount.checked_sub(destination_amount)?;
+    if new_swap_destination_amount == 0 {
+        return None;
+    }
+
+    let d = compute_d(amp_factor, swap_source_amount, swap_destination_amount)?;
+    let new_swap_source_amount = compute_new_destination_amount(
+        amp_factor, 
+        new_swap_destination_amount, 
+        d
+    )?;
+    let source_amount_less_fee = new_swap_source_amount.checked_sub(swap_source_amount)?;
+
+    let source_amount_swapped = pre_trading_fee_amoun(source_amount_less_fee, trade_fee_amount)?;
+
+    Some((source_amount_swapped, destination_amount))
+}
+
+
+/// StableSwap 曲线下根据存入的单币数量，计算可以获得的池子代币数量
+pub fn stable_deposit_single_token_type(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_other_amount: u128,
+    pool_supply: u128
+) -> Option<u128> {
+    let half_source_amount = std::cmp::max(1, source_amount.checked_div(2)?);
+    let trade_fee = calculation_fee(half_source_amount, trade_fee_amount)?;
+    let source_amount = source_amount.checked_sub(trade_fee)?;
+
+    let d0 = compute_d(amp_factor, swap_source_amount, swap_other_amount)?;
+    let d1 = compute_d(
+        amp_factor, 
+        swap_source_amount.checked_add(source_amount)?, 
+        swap_other_amount
+    )?;
+    let diff = d1.checked_sub(d0)?;
+
+    let pool_tokens = U256::from(pool_supply)
+        .checked_mul(U256::from(diff))?
+        .checked_div(U256::from(d0))?;
+
+    u128::try_from(pool_tokens).ok()
+}
+
+
+/// StableSwap 曲线下根据期望获得的池子代币数量，计算需要存入的单币数量(向上取整)
+pub fn stable_deposit_single_token_type_exact_out(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    pool_tokens: u128,
+    swap_source_amount: u128,
+    swap_other_amount: u128,
+    pool_supply: u128,
+) -> Option<u128> {
+    // 存入后的不变量按池币比例放大(向上取整)
+    let d0 = compute_d(amp_factor, swap_source_amount, swap_other_amount)?;
+    let numerator = U256::from(d0)
+        .checked_mul(U256::from(pool_supply.checked_add(pool_tokens)?))?;
+    let pool_supply = U256::from(pool_supply);
+    let mut d1 = numerator.checked_div(pool_supply)?;
+    if !numerator.checked_rem(pool_supply)?.is_zero() {
+        d1 = d1.checked_add(U256::one())?;
+    }
+
+    let new_swap_source_amount = compute_new_destination_amount(
+        amp_factor, 
+        swap_other_amount, 
+        u128::try_from(d1).ok()?
+    )?;
+    let source_amount = new_swap_source_amount.checked_sub(swap_source_amount)?;
+
+    gross_up_half_trade_fee(source_amount, trade_fee_amount)
+}
+
+
+/// StableSwap 曲线下根据单币提取数量，计算需要销毁的池子代币数量
+pub fn stable_withdraw_single_token_type_exact_out(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    source_amount: u128,
+    swap_source_amount: u128,
+    swap_other_amount: u128,
+    pool_supply: u128,
+) -> Option<u128> {
+    let source_amount = gross_up_half_trade_fee(source_amount, trade_fee_amount)?;
+
+    let d0 = compute_d(amp_factor, swap_source_amount, swap_other_amount)?;
+    let d1 = compute_d(
+        amp_factor, 
+        swap_source_amount.checked_sub(source_amount)?, 
+        swap_other_amount
+    )?;
+    let diff = d0.checked_sub(d1)?;
+
+    let numerator = U256::from(pool_supply).checked_mul(U256::from(diff))?;
+    let d0 = U256::from(d0);
+    let mut pool_tokens = numerator.checked_div(d0)?;
+    if !numerator.checked_rem(d0)?.is_zero() {
+        pool_tokens = pool_tokens.checked_add(U256::one())?;
+    }
+
+    u128::try_from(pool_tokens).ok()
+}
+
+/// StableSwap 曲线下根据销毁的池子代币数量，计算可以提取的单币数量(向下取整)
+pub fn stable_withdraw_single_token_type_exact_in(
+    amp_factor: u64,
+    trade_fee_amount: u128,
+    pool_tokens: u128,
+    swap_source_amount: u128,
+    swap_other_amount: u128,
+    pool_supply: u128,
+) -> Option<u128> {
+    // 提取后的不变量按剩余池币比例缩小(向上取整)
+    let d0 = compute_d(amp_factor, swap_source_amount, swap_other_amount)?;
+    let numerator = U256::from(d0)
+        .checked_mul(U256::from(pool_supply.checked_sub(pool_tokens)?))?;
+    let pool_supply = U256::from(pool_supply);
+    let mut d1 = numerator.checked_div(pool_supply)?;
+    if !numerator.checked_rem(pool_supply)?.is_zero() {
+        d1 = d1.checked_add(U256::one())?;
+    }
+
+    let new_swap_source_amount = compute_new_destination_amount(
+        amp_factor, 
+        swap_other_amount, 
+        u128::try_from(d1).ok()?
+    )?;
+    let source_amount = swap_source_amount.checked_sub(new_swap_source_amount)?;
+
+    deduct_half_trade_fee(source_amount, trade_fee_amount)
+}
+
+
+// ----------------------------------------------------------------------
+// 定价曲线抽象
+// ----------------------------------------------------------------------
+
+/// 定价曲线，指令通过 `Swap::curve()` 获取并调用，新增曲线只需实现该 trait
+pub trait CurveCalculator {
+    /// 精确输入兑换，返回 (实际参与兑换的源代币数量, 能兑换到的目标代币数量)
+    fn swap(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)>;
+
+    /// 精确输出兑换，返回 (需要投入的源代币数量, 目标代币数量)
+    fn swap_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)>;
+
+    /// 根据存入的单币数量，计算可以获得的池子代币数量
+    fn deposit_single_token_type(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128>;
+
+    /// 根据期望获得的池子代币数量，计算需要存入的单币数量
+    fn deposit_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128>;
+
+    /// 根据单币提取数量，计算需要销毁的池子代币数量
+    fn withdraw_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128>;
+
+    /// 根据销毁的池子代币数量，计算可以提取的单币数量
+    fn withdraw_single_token_type_exact_in(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128>;
+
+    /// 池子代币对应的双币数量，按比例计算，对所有曲线通用
+    fn pool_tokens_to_trading_tokens(
+        &self,
+        ceiling: bool,
+        pool_tokens: u128,
+        pool_token_supply: u128,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+    ) -> Option<(u128, u128)> {
+        pool_tokens_to_trading_tokens(
+            ceiling, 
+            pool_tokens, 
+            pool_token_supply, 
+            swap_token_a_amount, 
+            swap_token_b_amount
+        )
+    }
+
+    /// 池子总价值(以单币计)，用于衡量每个池子代币的价值
+    fn normalized_value(
+        &self,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+    ) -> Option<u128>;
+}
+
+
+/// 恒定乘积曲线 x·y=k
+pub struct ConstantProductCurve;
+
+impl CurveCalculator for ConstantProductCurve {
+    fn swap(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)> {
+        calculate_exchange_amount(
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            swap_destination_amount
+        )
+    }
+
+    fn swap_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)> {
+        calculate_exchange_amount_exact_out(
+            trade_fee_amount, 
+            destination_amount, 
+            swap_source_amount, 
+            swap_destination_amount
+        )
+    }
+
+    fn deposit_single_token_type(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        _swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        deposit_single_token_type(
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            pool_supply
+        )
+    }
+
+    fn deposit_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        _swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        deposit_single_token_type_exact_out(
+            trade_fee_amount, 
+            pool_tokens, 
+            swap_source_amount, 
+            pool_supply
+        )
+    }
+
+    fn withdraw_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        _swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        withdraw_single_token_type_exact_out(
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            pool_supply
+        )
+    }
+
+    fn withdraw_single_token_type_exact_in(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        _swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        withdraw_single_token_type_exact_in(
+            trade_fee_amount, 
+            pool_tokens, 
+            swap_source_amount, 
+            pool_supply
+        )
+    }
+
+    /// sqrt(x·y)
+    fn normalized_value(
+        &self,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+    ) -> Option<u128> {
+        sqrt(swap_token_a_amount.checked_mul(swap_token_b_amount)?)
+    }
+}
+
+
+/// StableSwap 曲线
+pub struct StableCurve {
+    pub amp_factor: u64,
+}
+
+impl CurveCalculator for StableCurve {
+    fn swap(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)> {
+        stable_calculate_exchange_amount(
+            self.amp_factor, 
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            swap_destination_amount
+        )
+    }
+
+    fn swap_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        destination_amount: u128,
+        swap_source_amount: u128,
+        swap_destination_amount: u128,
+    ) -> Option<(u128, u128)> {
+        stable_calculate_exchange_amount_exact_out(
+            self.amp_factor, 
+            trade_fee_amount, 
+            destination_amount, 
+            swap_source_amount, 
+            swap_destination_amount
+        )
+    }
+
+    fn deposit_single_token_type(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        stable_deposit_single_token_type(
+            self.amp_factor, 
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            swap_other_amount, 
+            pool_supply
+        )
+    }
+
+    fn deposit_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        stable_deposit_single_token_type_exact_out(
+            self.amp_factor, 
+            trade_fee_amount, 
+            pool_tokens, 
+            swap_source_amount, 
+            swap_other_amount, 
+            pool_supply
+        )
+    }
+
+    fn withdraw_single_token_type_exact_out(
+        &self,
+        trade_fee_amount: u128,
+        source_amount: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        stable_withdraw_single_token_type_exact_out(
+            self.amp_factor, 
+            trade_fee_amount, 
+            source_amount, 
+            swap_source_amount, 
+            swap_other_amount, 
+            pool_supply
+        )
+    }
+
+    fn withdraw_single_token_type_exact_in(
+        &self,
+        trade_fee_amount: u128,
+        pool_tokens: u128,
+        swap_source_amount: u128,
+        swap_other_amount: u128,
+        pool_supply: u128,
+    ) -> Option<u128> {
+        stable_withdraw_single_token_type_exact_in(
+            self.amp_factor, 
+            trade_fee_amount, 
+            pool_tokens, 
+            swap_source_amount, 
+            swap_other_amount, 
+            pool_supply
+        )
+    }
+
+    /// D / n，池子平衡时与 sqrt(x·y) 相等
+    fn normalized_value(
+        &self,
+        swap_token_a_amount: u128,
+        swap_token_b_amount: u128,
+    ) -> Option<u128> {
+        compute_d(self.amp_factor, swap_token_a_amount, swap_token_b_amount)?
+            .checked_div(N_COINS)
+    }
+}
+
+
+// ----------------------------------------------------------------------
+// 池子不变量
+// ----------------------------------------------------------------------
+
+/// 每份 LP 对应的曲线价值快照。指令开始时 `load`，所有 CPI 结束后 `check`，
+/// 要求 `value / lp_supply` 不减少：恒定乘积为 sqrt(reserve_a·reserve_b)，
+/// 用平方后的乘积精确比较；StableSwap 按含息数量计算 D，前后各允许 1 的迭代误差
+pub struct PoolInvariant {
+    value: U256,
+    supply: u64,
+    scale_a: u128,
+    scale_b: u128,
+}
+
+impl PoolInvariant {
+    pub fn load(
+        swap: &Swap,
+        pool_mint: &InterfaceAccount<Mint>,
+        token_a_mint: &InterfaceAccount<Mint>,
+        token_b_mint: &InterfaceAccount<Mint>,
+    ) -> Result<Self> {
+        let scale_a = interest_scale(token_a_mint)?;
+        let scale_b = interest_scale(token_b_mint)?;
+        let value = Self::value(swap, scale_a, scale_b)?;
+        Ok(Self { value, supply: pool_mint.supply, scale_a, scale_b })
+    }
+
+    /// 重新加载金库与 LP mint，金库余额须覆盖记账储备，且每份 LP 的价值不减少
+    pub fn check<'info>(
+        &self,
+        swap: &Swap,
+        token_a: &mut InterfaceAccount<'info, TokenAccount>,
+        token_b: &mut InterfaceAccount<'info, TokenAccount>,
+        pool_mint: &mut InterfaceAccount<'info, Mint>,
+    ) -> Result<()> {
+        self.check_lent(swap, token_a, token_b, pool_mint, 0, 0)
+    }
+
+    /// 同 `check`，闪电贷借出期间金库余额加上尚未归还的 `lent_a` / `lent_b` 后须覆盖记账储备
+    pub fn check_lent<'info>(
+        &self,
+        swap: &Swap,
+        token_a: &mut InterfaceAccount<'info, TokenAccount>,
+        token_b: &mut InterfaceAccount<'info, TokenAccount>,
+        pool_mint: &mut InterfaceAccount<'info, Mint>,
+        lent_a: u64,
+        lent_b: u64,
+    ) -> Result<()> {
+        token_a.reload()?;
+        token_b.reload()?;
+        pool_mint.reload()?;
+        let vault_a = token_a.amount.checked_add(lent_a).ok_or(SwapError::CalculationFailure)?;
+        let vault_b = token_b.amount.checked_add(lent_b).ok_or(SwapError::CalculationFailure)?;
+        require!(
+            vault_a >= swap.reserve_a && vault_b >= swap.reserve_b,
+            SwapError::InvariantViolated
+        );
+
+        self.verify(swap, pool_mint.supply)
+    }
+
+    /// 以 `swap` 当前的记账储备和 LP 供应量 `supply_after` 比较每份 LP 的价值
+    pub fn verify(&self, swap: &Swap, supply_after: u64) -> Result<()> {
+        let value = Self::value(swap, self.scale_a, self.scale_b)?;
+        let (supply_before, supply_after) = (U256::from(self.supply), U256::from(supply_after));
+        let holds = if swap.curve_type == Swap::CURVE_CONSTANT_PRODUCT {
+            value.checked_mul(supply_before * supply_before)
+                .zip(self.value.checked_mul(supply_after * supply_after))
+                .map(|(after, before)| after >= before)
+        } else {
+            // `compute_d` 的牛顿迭代在相邻两次结果相差不超过 1 时停止，得到的 D 与精确解
+            // 至多相差 1。前后两次计算各自可能偏差 1 且方向相反，因此按最不利情况比较
+            // (D_after + 1) / supply_after >= (D_before - 1) / supply_before
+            (value + 1).checked_mul(supply_before)
+                .zip(self.value.saturating_sub(U256::one()).checked_mul(supply_after))
+                .map(|(after, before)| after >= before)
+        }.ok_or(SwapError::CalculationFailure)?;
+        require!(holds, SwapError::InvariantViolated);
+
+        Ok(())
+    }
+
+    /// 恒定乘积返回 reserve_a·reserve_b，StableSwap 返回含息数量的 D
+    fn value(swap: &Swap, scale_a: u128, scale_b: u128) -> Result<U256> {
+        if swap.curve_type == Swap::CURVE_CONSTANT_PRODUCT {
+            return Ok(U256::from(swap.reserve_a) * U256::from(swap.reserve_b));
+        }
+        let value = swap.curve()?.normalized_value(
+            scale_interest_amount(swap.reserve_a, scale_a, false)?, 
+            scale_interest_amount(swap.reserve_b, scale_b, false)?
+        ).ok_or(SwapError::CalculationFailure)?;
+        Ok(U256::from(value))
+    }
+}
+
+
+// ----------------------------------------------------------------------
+// 历史价格观测
+// ----------------------------------------------------------------------
+
+/// 查询 `now - seconds_ago` 时刻的累计值，供其他程序计算任意窗口的 TWAP。
+/// `slots` 为观测账户头部之后的槽位(见 `Observations::load_mut`)。
+/// 最新观测点之后用 `swap` 的累计价格和当前余额外推，两个观测点之间线性插值，
+/// 早于最旧观测点或晚于 `now` 时返回 None
+pub fn observe(
+    observations: &Observations,
+    slots: &[Observation],
+    swap: &Swap,
+    now: i64,
+    reserve_a: u64,
+    reserve_b: u64,
+    seconds_agos: &[u32],
+) -> Option<Vec<Observation>> {
+    let current = current_observation(observations.latest(slots), swap, now, reserve_a, reserve_b)?;
+    seconds_agos
+        .iter()
+        .map(|seconds_ago| {
+            observe_single(observations, slots, &current, now.checked_sub(i64::from(*seconds_ago))?)
+        })
+        .collect()
+}
+
+/// 把最新观测点外推到 `now`
+fn current_observation(
+    last: &Observation,
+    swap: &Swap,
+    now: i64,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Option<Observation> {
+    let price_elapsed = u128::try_from(now.checked_sub(swap.block_timestamp_last)?).ok()?;
+    let liquidity_elapsed = u128::try_from(now.checked_sub(last.block_timestamp)?).ok()?;
+
+    let (price_a, price_b) = Swap::spot_prices(reserve_a, reserve_b).unwrap_or((0, 0));
+    let liquidity = sqrt(u128::from(reserve_a).checked_mul(u128::from(reserve_b))?)?;
+
+    Some(Observation {
+        block_timestamp: now,
+        price_a_cumulative: swap.price_a_cumulative_last
+            .wrapping_add(price_a.wrapping_mul(price_elapsed)),
+        price_b_cumulative: swap.price_b_cumulative_last
+            .wrapping_add(price_b.wrapping_mul(price_elapsed)),
+        liquidity_cumulative: last.liquidity_cumulative
+            .wrapping_add(liquidity.wrapping_mul(liquidity_elapsed)),
+        ..Default::default()
+    })
+}
+
+fn observe_single(
+    observations: &Observations,
+    slots: &[Observation],
+    current: &Observation,
+    target: i64,
+) -> Option<Observation> {
+    let last = observations.latest(slots);
+    if target > current.block_timestamp {
+        return None;
+    }
+    if target >= last.block_timestamp {
+        return interpolate_observation(last, current, target);
+    }
+
+    // 环中最旧的观测点在 index 的下一个槽位，该槽位未写入时环尚未写满，最旧的是 0 号
+    let cardinality = usize::from(observations.cardinality);
+    let index = usize::from(observations.index);
+    let mut oldest = (index + 1) % cardinality;
+    if !slots[oldest].is_initialized() {
+        oldest = 0;
+    }
+    let count = if oldest == 0 { index + 1 } else { cardinality };
+    let at = |i: usize| &slots[(oldest + i) % cardinality];
+
+    if target < at(0).block_timestamp {
+        return None;
+    }
+
+    // 二分查找最后一个不晚于 target 的观测点
+    let (mut low, mut high) = (0, count - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if at(mid).block_timestamp <= target {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    interpolate_observation(at(low), at(low + 1), target)
+}
+
+/// 在 `before` 与 `after` 之间按时间线性插值，累计值允许回绕
+fn interpolate_observation(
+    before: &Observation,
+    after: &Observation,
+    target: i64,
+) -> Option<Observation> {
+    if target == before.block_timestamp {
+        return Some(*before);
+    }
+    let total = U256::from(u128::try_from(after.block_timestamp.checked_sub(before.block_timestamp)?).ok()?);
+    let elapsed = U256::from(u128::try_from(target.checked_sub(before.block_timestamp)?).ok()?);
+    let interpolate = |from: u128, to: u128| -> Option<u128> {
+        let delta = U256::from(to.wrapping_sub(from))
+            .checked_mul(elapsed)?
+            .checked_div(total)?;
+        Some(from.wrapping_add(u128::try_from(delta).ok()?))
+    };
+
+    Some(Observation {
+        block_timestamp: target,
+        price_a_cumulative: interpolate(before.price_a_cumulative, after.price_a_cumulative)?,
+        price_b_cumulative: interpolate(before.price_b_cumulative, after.price_b_cumulative)?,
+        liquidity_cumulative: interpolate(before.liquidity_cumulative, after.liquidity_cumulative)?,
+        ..Default::default()
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(curve_type: u8, reserve_a: u64, reserve_b: u64) -> Swap {
+        Swap { curve_type, amp_factor: 100, reserve_a, reserve_b, ..Swap::default() }
+    }
+
+    fn snapshot(swap: &Swap, supply: u64) -> PoolInvariant {
+        PoolInvariant {
+            value: PoolInvariant::value(swap, INTEREST_SCALE_ONE, INTEREST_SCALE_ONE).unwrap(),
+            supply,
+            scale_a: INTEREST_SCALE_ONE,
+            scale_b: INTEREST_SCALE_ONE,
+        }
+    }
+
+    #[test]
+    fn pool_invariant_rejects_lower_value_per_lp() {
+        for curve_type in [Swap::CURVE_CONSTANT_PRODUCT, Swap::CURVE_STABLE] {
+            let swap = pool(curve_type, 1_000_000_000, 1_000_000_000);
+            let invariant = snapshot(&swap, 1_000);
+            assert!(invariant.verify(&swap, 1_000).is_ok());
+
+            // 储备不变但多铸了池币
+            assert_eq!(
+                invariant.verify(&swap, 1_001).unwrap_err(),
+                error!(SwapError::InvariantViolated)
+            );
+
+            // LP 不变但储备被转走
+            let drained = pool(curve_type, 999_000_000, 1_000_000_000);
+            assert_eq!(
+                invariant.verify(&drained, 1_000).unwrap_err(),
+                error!(SwapError::InvariantViolated)
+            );
+
+            // 收取手续费后储备增加
+            let grown = pool(curve_type, 1_000_100_000, 1_000_000_000);
+            assert!(invariant.verify(&grown, 1_000).is_ok());
+        }
+    }
+
+    #[test]
+    fn compound_interest_scale_matches_exp() {
+        let year = SECONDS_PER_YEAR as i64;
+        // 年化 5% 一年，e^0.05 与 e^-0.05
+        assert_eq!(compound_interest_scale(0, 0, 0, 500, year), Some(1_051_271_096_376));
+        assert_eq!(compound_interest_scale(0, 0, 0, -500, year), Some(951_229_424_501));
+        // 100% 一年为 e
+        assert_eq!(compound_interest_scale(0, 0, 0, 10_000, year), Some(2_718_281_828_459));
+        // 先 +10% 半年，再 -10% 半年，恰好抵消
+        assert_eq!(compound_interest_scale(0, 1_000, year / 2, -1_000, year), Some(INTEREST_SCALE_ONE));
+        assert_eq!(compound_interest_scale(100, 0, 100, 32_767, 100), Some(INTEREST_SCALE_ONE));
+
+        for (rate, seconds) in [(1, 1), (32_767, 60), (-32_768, 86_400), (32_767, 4 * year), (-32_768, 4 * year)] {
+            let scale = compound_interest_scale(0, 0, 0, rate, seconds).unwrap() as f64;
+            let expected = (f64::from(rate) * seconds as f64 / SECONDS_PER_YEAR as f64 / 10_000.0).exp()
+                * INTEREST_SCALE_ONE as f64;
+            assert!((scale - expected).abs() <= expected * 1e-12 + 1.0, "{} {} {} {}", rate, seconds, scale, expected);
+        }
+    }
+
+    #[test]
+    fn compound_interest_scale_saturates_at_max_exponent() {
+        let year = SECONDS_PER_YEAR as i64;
+        // 最高利率下 x 恰好达到上限所需的秒数
+        let limit = (MAX_INTEREST_EXPONENT as i64) * year * 10_000 / 32_767 + 1;
+        let max_scale = compound_interest_scale(0, 0, 0, 32_767, limit).unwrap();
+        let expected = (MAX_INTEREST_EXPONENT as f64).exp() * INTEREST_SCALE_ONE as f64;
+        assert!((max_scale as f64 - expected).abs() <= expected * 1e-12);
+
+        // 超过上限后比例保持不变，不再返回 None
+        for seconds in [2 * limit, 100 * year, i64::from(u32::MAX), i64::MAX / 2] {
+            assert_eq!(compound_interest_scale(0, 0, 0, 32_767, seconds), Some(max_scale));
+            assert_eq!(
+                compound_interest_scale(0, 32_767, seconds, 32_767, 2 * seconds), 
+                Some(max_scale)
+            );
+            let min_scale = compound_interest_scale(0, 0, 0, -32_768, seconds).unwrap();
+            assert!(min_scale > 0);
+        }
+        assert!(compound_interest_scale(0, 0, 0, 32_767, limit - 2).unwrap() < max_scale);
+
+        // 上限下任意 u64 数量都能换算
+        let scaled = scale_interest_amount(u64::MAX, max_scale, true).unwrap();
+        assert_eq!(unscale_interest_amount(scaled, max_scale, false).unwrap(), u64::MAX);
+    }
+
+    /// 每秒累计价格增加 1_000，流动性恒为 7，第一个观测点在 t=10
+    fn ring(cardinality_next: u16, timestamps: &[i64]) -> (Observations, Vec<Observation>) {
+        let mut observations: Observations = bytemuck::Zeroable::zeroed();
+        observations.cardinality = 1;
+        observations.cardinality_next = cardinality_next;
+        let mut slots = vec![Observation::default(); usize::from(cardinality_next)];
+        slots[0] = Observation {
+            block_timestamp: 10,
+            price_a_cumulative: 10_000,
+            price_b_cumulative: 20_000,
+            ..Default::default()
+        };
+        for &timestamp in timestamps {
+            let cumulative = timestamp as u128 * 1_000;
+            observations.write(&mut slots, timestamp, cumulative, cumulative * 2, 7);
+        }
+        (observations, slots)
+    }
+
+    #[test]
+    fn observation_ring_wraps_around() {
+        // 写入 t=20..60 后环为 [50, 60, 30, 40]，最旧的 10、20 已被覆盖，同一秒只写一次
+        let (observations, slots) = ring(4, &[20, 30, 30, 40, 50, 60]);
+        assert_eq!(observations.cardinality, 4);
+        assert_eq!(observations.index, 1);
+        assert_eq!(
+            slots.iter().map(|slot| slot.block_timestamp).collect::<Vec<_>>(),
+            vec![50, 60, 30, 40]
+        );
+        let current = *observations.latest(&slots);
+
+        // 落在观测点上
+        let at_30 = observe_single(&observations, &slots, &current, 30).unwrap();
+        assert_eq!(at_30.price_a_cumulative, 30_000);
+        assert_eq!(at_30.liquidity_cumulative, slots[2].liquidity_cumulative);
+
+        // 跨过环尾的两个观测点之间插值
+        let at_55 = observe_single(&observations, &slots, &current, 55).unwrap();
+        assert_eq!(at_55.price_a_cumulative, 55_000);
+        assert_eq!(at_55.price_b_cumulative, 110_000);
+        assert_eq!(at_55.liquidity_cumulative, slots[0].liquidity_cumulative + 35);
+
+        let at_35 = observe_single(&observations, &slots, &current, 35).unwrap();
+        assert_eq!(at_35.price_a_cumulative, 35_000);
+
+        // 早于最旧观测点、晚于当前时刻
+        assert!(observe_single(&observations, &slots, &current, 25).is_none());
+        assert!(observe_single(&observations, &slots, &current, 61).is_none());
+    }
+
+    #[test]
+    fn observation_ring_not_yet_full() {
+        // 只写入到 2 号槽位时最旧的是 0 号
+        let (observations, slots) = ring(4, &[20, 40]);
+        let current = *observations.latest(&slots);
+        assert_eq!(observe_single(&observations, &slots, &current, 10).unwrap().price_a_cumulative, 10_000);
+        assert_eq!(observe_single(&observations, &slots, &current, 25).unwrap().price_a_cumulative, 25_000);
+        assert!(observe_single(&observations, &slots, &current, 9).is_none());
+    }
+
+    #[test]
+    fn observe_extrapolates_from_swap() {
+        let (observations, slots) = ring(4, &[20, 30]);
+        let swap = Swap {
+            price_a_cumulative_last: 30_000,
+            price_b_cumulative_last: 60_000,
+            block_timestamp_last: 30,
+            ..Swap::default()
+        };
+        // 储备 1:2，price_a = 2，price_b = 0.5
+        let result = observe(&observations, &slots, &swap, 40, 1_000, 2_000, &[0, 5, 15]).unwrap();
+
+        let one = 1u128 << 64;
+        assert_eq!(result[0].block_timestamp, 40);
+        assert_eq!(result[0].price_a_cumulative, 30_000 + 2 * one * 10);
+        assert_eq!(result[0].price_b_cumulative, 60_000 + one / 2 * 10);
+        assert_eq!(result[1].price_a_cumulative, 30_000 + 2 * one * 5);
+        assert_eq!(result[2].price_a_cumulative, 25_000);
+
+        // 任一时刻早于最旧观测点时整体返回 None
+        assert!(observe(&observations, &slots, &swap, 40, 1_000, 2_000, &[0, 31]).is_none());
+    }
+
+    #[test]
+    fn interpolate_observation_wraps_cumulative() {
+        let before = Observation {
+            block_timestamp: 100,
+            price_a_cumulative: u128::MAX - 9,
+            ..Default::default()
+        };
+        let after = Observation {
+            block_timestamp: 110,
+            price_a_cumulative: 10,
+            ..Default::default()
+        };
+        assert_eq!(interpolate_observation(&before, &after, 105).unwrap().price_a_cumulative, 0);
+        assert_eq!(interpolate_observation(&before, &after, 110).unwrap().price_a_cumulative, 10);
+        assert_eq!(interpolate_observation(&before, &after, 100).unwrap().price_a_cumulative, u128::MAX - 9);
+    }
+
+    #[test]
+    fn deduct_half_trade_fee_inverts_gross_up() {
+        for fee in [0, 1, 30, 100, 2_500, 5_000, 9_000, 9_999] {
+            for amount in (1..2_000).chain([1_000_003, 123_456_789, u64::MAX as u128]) {
+                let gross = gross_up_half_trade_fee(amount, fee).unwrap();
+                let net = deduct_half_trade_fee(gross, fee).unwrap();
+                assert!(net >= amount && net <= amount + 1, "fee {fee} amount {amount}: {net}");
+            }
+        }
+
+        // 50% 手续费下提取 100 到账 100 · 1 / 1.5
+        assert_eq!(deduct_half_trade_fee(100, 5_000), Some(66));
+        assert_eq!(deduct_half_trade_fee(100, 0), Some(100));
+    }
+}
@@ -0,0 +1,122 @@
+//! 转出金库中超出记账储备的代币(仅管理员)
+//! 直接转入金库的代币不计入储备，不影响定价，可由管理员转给指定账户
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{error::SwapError, events::SkimEvent, shared::transfer_tokens, state::Swap};
+
+
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        has_one = admin @ SwapError::Unauthorized,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        address = swap.token_a_mint
+    )]
+    pub token_a_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        address = swap.token_b_mint
+    )]
+    pub token_b_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
+        token::authority = swap
+    )]
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_a_mint
+    )]
+    pub recipient_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = token_b_mint
+    )]
+    pub recipient_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+
+impl<'info> Skim<'info> {
+    pub fn process(&mut self, remaining_accounts: &[AccountInfo<'info>]) -> Result<()> {
+        let amount_a = self.token_a.amount.saturating_sub(self.swap.reserve_a);
+        let amount_b = self.token_b.amount.saturating_sub(self.swap.reserve_b);
+        require!(amount_a > 0 || amount_b > 0, SwapError::ZeroTradingTokens);
+
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            Swap::SWAP_SEEDS,
+            self.swap.token_a_mint.as_ref(),
+            self.swap.token_b_mint.as_ref(),
+            &[self.swap.swap_bump_seed]
+        ]];
+        if amount_a > 0 {
+            transfer_tokens(
+                &self.token_a, 
+                &self.recipient_token_a, 
+                amount_a, 
+                &self.token_a_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+            msg!("转出多余 token_a: {}", amount_a);
+        }
+        if amount_b > 0 {
+            transfer_tokens(
+                &self.token_b, 
+                &self.recipient_token_b, 
+                amount_b, 
+                &self.token_b_mint, 
+                self.swap.to_account_info(), 
+                &self.token_program, 
+                Some(signer_seeds), 
+                remaining_accounts
+            )?;
+            msg!("转出多余 token_b: {}", amount_b);
+        }
+
+        emit!(SkimEvent {
+            swap: self.swap.key(),
+            admin: self.admin.key(),
+            amount_a,
+            amount_b,
+        });
+
+        Ok(())
+    }
+}
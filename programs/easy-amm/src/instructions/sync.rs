@@ -0,0 +1,109 @@
+//! 以金库余额重置记账储备(仅管理员)
+//! 直接转入金库的代币因此计入储备，归 LP 所有
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+
+use crate::{error::SwapError, events::SyncEvent, shared::PoolInvariant, state::{Observations, Swap}};
+
+
+#[derive(Accounts)]
+pub struct Sync<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        has_one = admin @ SwapError::Unauthorized,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+
+    #[account(
+        address = swap.token_a_mint
+    )]
+    pub token_a_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        address = swap.token_b_mint
+    )]
+    pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
+        token::authority = swap
+    )]
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            swap.key().as_ref(),
+            Swap::POOL_MINT_SEEDS
+        ],
+        bump = swap.pool_mint_bump_seed,
+        mint::authority = swap
+    )]
+    pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+}
+
+
+impl<'info> Sync<'info> {
+    pub fn process(&mut self) -> Result<()> {
+        // 以同步前的储备更新 TWAP 累计价格
+        self.swap.update_price_oracle(&self.observations)?;
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
+        let (old_reserve_a, old_reserve_b) = (self.swap.reserve_a, self.swap.reserve_b);
+        self.swap.reserve_a = self.token_a.amount;
+        self.swap.reserve_b = self.token_b.amount;
+        msg!(
+            "同步储备: a {} -> {}, b {} -> {}", 
+            old_reserve_a, 
+            self.swap.reserve_a, 
+            old_reserve_b, 
+            self.swap.reserve_b
+        );
+
+        emit!(SyncEvent {
+            swap: self.swap.key(),
+            admin: self.admin.key(),
+            old_reserve_a,
+            old_reserve_b,
+            reserve_a: self.swap.reserve_a,
+            reserve_b: self.swap.reserve_b,
+        });
+
+        // 同步只会计入多出的余额，每份 LP 的价值不能因此减少
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
+    }
+}
@@ -0,0 +1,73 @@
+//! 更新手续费(仅管理员)
+
+use anchor_lang::prelude::*;
+
+use crate::{error::SwapError, events::FeesUpdatedEvent, state::Swap};
+
+
+#[derive(Accounts)]
+pub struct UpdateFees<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        has_one = admin @ SwapError::Unauthorized,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
+    )]
+    pub swap: Account<'info, Swap>,
+}
+
+
+impl<'info> UpdateFees<'info> {
+    pub fn process(
+        &mut self,
+        trade_fees: u16,
+        withdraw_fees: u16,
+        owner_trade_fees: u16,
+        host_fees: u16,
+        flash_fees: u16
+    ) -> Result<()> {
+        Swap::validate_fees(trade_fees, withdraw_fees, owner_trade_fees, host_fees, flash_fees)?;
+
+        let old_trade_fees = self.swap.trade_fees;
+        let old_withdraw_fees = self.swap.withdraw_fees;
+        let old_owner_trade_fees = self.swap.owner_trade_fees;
+        let old_host_fees = self.swap.host_fees;
+        let old_flash_fees = self.swap.flash_fees;
+
+        self.swap.trade_fees = trade_fees;
+        self.swap.withdraw_fees = withdraw_fees;
+        self.swap.owner_trade_fees = owner_trade_fees;
+        self.swap.host_fees = host_fees;
+        self.swap.flash_fees = flash_fees;
+        msg!("更新手续费: trade {} -> {}, withdraw {} -> {}, owner {} -> {}, host {} -> {}, flash {} -> {}", 
+            old_trade_fees, trade_fees, 
+            old_withdraw_fees, withdraw_fees, 
+            old_owner_trade_fees, owner_trade_fees,
+            old_host_fees, host_fees,
+            old_flash_fees, flash_fees);
+
+        emit!(FeesUpdatedEvent {
+            swap: self.swap.key(),
+            admin: self.admin.key(),
+            old_trade_fees,
+            old_withdraw_fees,
+            old_owner_trade_fees,
+            old_host_fees,
+            old_flash_fees,
+            trade_fees,
+            withdraw_fees,
+            owner_trade_fees,
+            host_fees,
+            flash_fees,
+        });
+
+        Ok(())
+    }
+}
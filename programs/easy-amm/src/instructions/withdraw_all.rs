@@ -6,12 +6,17 @@ use anchor_spl::{
 
 use crate::{
     error::SwapError, events::WithdrawAllEvent, shared::{
+        amount_after_transfer_fee, 
         burn_tokens, 
         calculation_fee, 
-        pool_tokens_to_trading_tokens,
+        check_deadline, 
+        PoolInvariant, 
         to_u64, 
-        transfer_tokens
-    }, state::Swap
+        transfer_token_infos, 
+        transfer_tokens, 
+        unwrap_native, 
+        UserTokenAccount
+    }, state::{Observations, Swap}
 };
 
 
@@ -31,8 +36,15 @@ pub struct WithdrawAll<'info> {
     pub token_b_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
-        seeds = [Swap::SWAP_SEEDS],
-        bump
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_WITHDRAW) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
     )]
     pub swap: Box<Account<'info, Swap>>,
 
@@ -65,54 +77,87 @@ pub struct WithdrawAll<'info> {
             Swap::POOL_MINT_SEEDS
         ],
         bump = swap.pool_mint_bump_seed,
-        mint::authority = swap
+        mint::authority = swap,
+        mint::token_program = lp_token_program
     )]
     pub pool_mint: Box<InterfaceAccount<'info, Mint>>,
 
+    /// 历史价格观测账户，改变储备前写入观测点
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = token_a_mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
     )]
-    pub user_token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub observations: AccountLoader<'info, Observations>,
 
+    /// CHECK: user 在 token_a_mint 下的 ATA，不存在时在 process 中创建
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = token_b_mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_a_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub user_token_a: UncheckedAccount<'info>,
+
+    /// CHECK: user 在 token_b_mint 下的 ATA，不存在时在 process 中创建
+    #[account(
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            token_b_mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
+    )]
+    pub user_token_b: UncheckedAccount<'info>,
 
     #[account(
         mut,
         associated_token::mint = swap.pool_mint,
-        associated_token::authority = user
+        associated_token::authority = user,
+        associated_token::token_program = lp_token_program
     )]
     pub user_mint_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         address = swap.pool_fee_account,
-        token::mint = swap.pool_mint
+        token::mint = swap.pool_mint,
+        token::token_program = lp_token_program
     )]
     pub pool_fee_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>
 }
 
 
 impl<'info> WithdrawAll<'info> {
     pub fn process(
-        &self, 
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         token_amount: u64,
         minimum_token_a_amount: u64,
         minimum_token_b_amount: u64,
-        bump_swap: u8
+        deadline: Option<i64>,
     ) -> Result<()> {
+        check_deadline(deadline)?;
+
+        // 以操作前的储备更新 TWAP 累计价格
+        self.swap.update_price_oracle(&self.observations)?;
+        let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.token_a_mint, &self.token_b_mint)?;
+
         require_gt!(token_amount, Swap::MIN_TOKEN_AMOUNT, SwapError::WithdrawTooSmall);
         require!(
             token_amount <= self.user_mint_account.amount,
@@ -134,36 +179,39 @@ impl<'info> WithdrawAll<'info> {
             .checked_sub(withdraw_fee)
             .ok_or(SwapError::CalculationFailure)?;
 
-        let (token_a_amount, token_b_amount) = pool_tokens_to_trading_tokens(
+        let (token_a_amount, token_b_amount) = self.swap.curve()?.pool_tokens_to_trading_tokens(
             false,
             u128::from(token_amount), 
             u128::from(self.pool_mint.supply), 
-            u128::from(self.token_a.amount), 
-            u128::from(self.token_b.amount)
+            u128::from(self.swap.reserve_a), 
+            u128::from(self.swap.reserve_b)
             ).ok_or(SwapError::ZeroTradingTokens)?;
 
         let token_a_amount = to_u64(token_a_amount)?;
         let token_b_amount = to_u64(token_b_amount)?;
 
-        let token_a_amount = std::cmp::min(self.token_a.amount, token_a_amount);
-        let token_b_amount = std::cmp::min(self.token_b.amount, token_b_amount);
+        let token_a_amount = std::cmp::min(self.swap.reserve_a, token_a_amount);
+        let token_b_amount = std::cmp::min(self.swap.reserve_b, token_b_amount);
 
-        if token_a_amount < minimum_token_a_amount {
+        // 以用户扣除转账手续费后实际收到的数量判断滑点
+        if amount_after_transfer_fee(token_a_amount, &self.token_a_mint)? < minimum_token_a_amount {
             return err!(SwapError::ExceededSlippage);
         }
 
-        if token_a_amount == 0 && self.token_a.amount == 0 {
+        if token_a_amount == 0 && self.swap.reserve_a == 0 {
             return err!(SwapError::ZeroTradingTokens);
         }
 
-        if token_b_amount < minimum_token_b_amount {
+        if amount_after_transfer_fee(token_b_amount, &self.token_b_mint)? < minimum_token_b_amount {
             return err!(SwapError::ExceededSlippage);
         }
 
-        if token_b_amount == 0 && self.token_b.amount == 0 {
+        if token_b_amount == 0 && self.swap.reserve_b == 0 {
             return err!(SwapError::ZeroTradingTokens);
         }
 
+        self.swap.withdraw_reserves(token_a_amount, token_b_amount)?;
+
         // 转账
         if withdraw_fee > 0 {
             transfer_tokens(
@@ -172,8 +220,9 @@ impl<'info> WithdrawAll<'info> {
                 withdraw_fee, 
                 &self.pool_mint, 
                 self.user.to_account_info(), 
-                &self.token_program,
-                None
+                &self.lp_token_program,
+                None, 
+                remaining_accounts
             )?;
             msg!("收取提取手续费(双币): {}", withdraw_fee);
         }
@@ -183,43 +232,71 @@ impl<'info> WithdrawAll<'info> {
             &self.user_mint_account, 
             &self.pool_mint, 
             self.user.to_account_info(), 
-            &self.token_program, 
+            &self.lp_token_program, 
             token_amount
         )?;
 
+        // 用户 ATA 不存在时创建
+        let user_token_a = UserTokenAccount::load_or_create(
+            self.user_token_a.to_account_info(), 
+            &self.user, 
+            &self.token_a_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        let user_token_b = UserTokenAccount::load_or_create(
+            self.user_token_b.to_account_info(), 
+            &self.user, 
+            &self.token_b_mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+
         // 转账
         if token_a_amount > 0 {
-            transfer_tokens(
-                &self.token_a, 
-                &self.user_token_a, 
+            transfer_token_infos(
+                self.token_a.to_account_info(), 
+                user_token_a.to_account_info(), 
                 token_a_amount, 
                 &self.token_a_mint, 
                 self.swap.to_account_info(), 
                 &self.token_program, 
                 Some(&[&[
                     Swap::SWAP_SEEDS,
-                    &[bump_swap]
-                ]])
+                    self.swap.token_a_mint.as_ref(),
+                    self.swap.token_b_mint.as_ref(),
+                    &[self.swap.swap_bump_seed]
+                ]]), 
+                remaining_accounts
             )?;
             msg!("提取token_a: {}", token_a_amount);
         }
 
         if token_b_amount > 0 {
-            transfer_tokens(
-                &self.token_b, 
-                &self.user_token_b, 
+            transfer_token_infos(
+                self.token_b.to_account_info(), 
+                user_token_b.to_account_info(), 
                 token_b_amount, 
                 &self.token_b_mint, 
                 self.swap.to_account_info(), 
                 &self.token_program, 
                 Some(&[&[
                     Swap::SWAP_SEEDS,
-                    &[bump_swap]
-                ]])
+                    self.swap.token_a_mint.as_ref(),
+                    self.swap.token_b_mint.as_ref(),
+                    &[self.swap.swap_bump_seed]
+                ]]), 
+                remaining_accounts
             )?;
             msg!("提取token_b: {}", token_b_amount);
         }
 
+        // 原生 SOL 一侧的 wSOL 账户为本指令新建时解包给用户
+        unwrap_native(&self.user, &user_token_a, &self.token_program)?;
+        unwrap_native(&self.user, &user_token_b, &self.token_program)?;
+
         emit!(WithdrawAllEvent {
             user: self.user.key(),
             pool_amount: token_amount,
@@ -228,6 +305,6 @@ impl<'info> WithdrawAll<'info> {
             withdraw_fee,
         });
 
-        Ok(())
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
     }
 }
@@ -1,7 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{associated_token::AssociatedToken, token_interface::{Mint, TokenAccount, TokenInterface}};
 
-use crate::{error::SwapError, events::WithdrawSingleEvent, shared::{burn_tokens, calculation_fee, to_u64, transfer_tokens, withdraw_single_token_type_exact_out}, state::Swap};
+use crate::{error::SwapError, events::WithdrawSingleEvent, shared::{amount_after_transfer_fee, amount_before_transfer_fee, burn_tokens, calculation_fee, check_deadline, interest_scale, PoolInvariant, scale_interest_amount, to_u64, transfer_token_infos, transfer_tokens, unscale_interest_amount, unwrap_native, UserTokenAccount}, state::{Observations, Swap}};
 
 
 #[derive(Accounts)]
@@ -12,17 +12,47 @@ pub struct WithdrawSingle<'info> {
     pub mint: InterfaceAccount<'info, Mint>,
 
     #[account(
-        seeds = [Swap::SWAP_SEEDS],
-        bump
+        mut,
+        seeds = [
+            Swap::SWAP_SEEDS,
+            swap.token_a_mint.as_ref(),
+            swap.token_b_mint.as_ref()
+        ],
+        bump = swap.swap_bump_seed,
+        constraint = !swap.is_paused(Swap::PAUSE_WITHDRAW) @ SwapError::PoolPaused,
+        constraint = !swap.flash_loan_locked @ SwapError::PoolLocked
     )]
     pub swap: Account<'info, Swap>,
 
+    /// 池子另一侧代币的 mint，用于按计息扩展换算含息数量
+    #[account(
+        constraint = (mint.key() == swap.token_a_mint && other_mint.key() == swap.token_b_mint)
+            || (mint.key() == swap.token_b_mint && other_mint.key() == swap.token_a_mint)
+            @ SwapError::InvalidMint
+    )]
+    pub other_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_A_SEEDS
+        ],
+        bump = swap.token_a_bump_seed,
+        token::authority = swap
+    )]
+    pub token_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
     #[account(
         mut,
-        token::mint = mint,
+        seeds = [
+            swap.key().as_ref(),
+            Swap::TOKEN_B_SEEDS
+        ],
+        bump = swap.token_b_bump_seed,
         token::authority = swap
     )]
-    pub pool_token: InterfaceAccount<'info, TokenAccount>,
+    pub token_b: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
@@ -31,86 +61,213 @@ pub struct WithdrawSingle<'info> {
             Swap::POOL_MINT_SEEDS
         ],
         bump = swap.pool_mint_bump_seed,
-        mint::authority = swap
+        mint::authority = swap,
+        mint::token_program = lp_token_program
     )]
     pub pool_mint: InterfaceAccount<'info, Mint>,
 
+    /// 历史价格观测账户，改变储备前写入观测点
+    #[account(
+        mut,
+        seeds = [
+            swap.key().as_ref(),
+            Observations::OBSERVATION_SEEDS
+        ],
+        bump = observations.load()?.bump
+    )]
+    pub observations: AccountLoader<'info, Observations>,
+
     #[account(
         mut,
         associated_token::mint = swap.pool_mint,
-        associated_token::authority = user
+        associated_token::authority = user,
+        associated_token::token_program = lp_token_program
     )]
     pub user_mint_account: InterfaceAccount<'info, TokenAccount>,
 
+    /// CHECK: user 在 mint 下的 ATA，不存在时在 process 中创建
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            user.key().as_ref(),
+            token_program.key().as_ref(),
+            mint.key().as_ref()
+        ],
+        seeds::program = associated_token_program.key(),
+        bump
     )]
-    pub user_token: InterfaceAccount<'info, TokenAccount>,
+    pub user_token: UncheckedAccount<'info>,
 
     #[account(
         mut,
         address = swap.pool_fee_account,
-        token::mint = swap.pool_mint
+        token::mint = swap.pool_mint,
+        token::token_program = lp_token_program
     )]
     pub pool_fee_account: InterfaceAccount<'info, TokenAccount>,
 
     pub system_program: Program<'info, System>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// LP mint 所属的 token 程序，可与池子两侧代币的 token 程序不同
+    pub lp_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>
 }
 
 
 impl<'info> WithdrawSingle<'info> {
     pub fn process(
-        &self, 
-        bump_swap: u8,
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
         destination_token_amount: u64,
         maximum_pool_token_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
+        let (invariant, is_token_a) = self.prepare(deadline)?;
+
         require_gt!(destination_token_amount, 0, SwapError::WithdrawTooSmall);
-        if self.mint.key() != self.swap.token_a_mint 
-            && self.mint.key() != self.swap.token_b_mint 
-        {
-            return err!(SwapError::InvalidMint);
-        }
+        let (swap_token_amount, reserve_other) = self.swap.reserves(is_token_a);
+        // 池子需要转出的数量(用户扣除转账手续费后正好收到 destination_token_amount)
+        let transfer_amount = amount_before_transfer_fee(destination_token_amount, &self.mint)?;
 
-        let swap_token_amount = self.pool_token.amount;
-        // 计算需要消耗的池币
-        let burn_pool_token_amount = withdraw_single_token_type_exact_out(
+        // 计算需要消耗的池币(换算成含息数量)
+        let source_scale = interest_scale(&self.mint)?;
+        let other_scale = interest_scale(&self.other_mint)?;
+        let burn_pool_token_amount = self.swap.curve()?.withdraw_single_token_type_exact_out(
             u128::from(self.swap.trade_fees), 
-            u128::from(destination_token_amount), 
-            u128::from(swap_token_amount), 
+            scale_interest_amount(transfer_amount, source_scale, true)?, 
+            scale_interest_amount(swap_token_amount, source_scale, false)?, 
+            scale_interest_amount(reserve_other, other_scale, false)?, 
             u128::from(self.pool_mint.supply)
         ).ok_or(SwapError::ZeroTradingTokens)?;
+        let burn_pool_token_amount = to_u64(burn_pool_token_amount)?;
 
-        // 计算手续费
-        let withdraw_fee = if self.pool_fee_account.key() == self.user_mint_account.key() {
-            0
-        } else {
-            calculation_fee(
-                u128::from(burn_pool_token_amount), 
-                u128::from(self.swap.withdraw_fees)
-            ).ok_or(SwapError::FeeCalculationFailure)?
-        };
-
+        // 提取手续费在销毁量之外另收
+        let withdraw_fee = self.withdraw_fee(burn_pool_token_amount)?;
         let pool_token_amount = burn_pool_token_amount
             .checked_add(withdraw_fee)
             .ok_or(SwapError::CalculationFailure)?;
-        
-        let pool_token_amount = to_u64(pool_token_amount)?;
 
         if pool_token_amount > maximum_pool_token_amount {
             return err!(SwapError::ExceededSlippage);
         }
 
+        self.settle(
+            remaining_accounts, 
+            invariant, 
+            is_token_a, 
+            pool_token_amount, 
+            withdraw_fee, 
+            transfer_amount, 
+            destination_token_amount
+        )
+    }
+
+    /// 精确输入单币提取：销毁 `pool_token_amount` 池币(提取手续费从中扣除)，
+    /// 按曲线计算可提取的单币数量，到账数量不少于 `minimum_token_amount`
+    pub fn process_exact_in(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        pool_token_amount: u64,
+        minimum_token_amount: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        let (invariant, is_token_a) = self.prepare(deadline)?;
+
+        require_gt!(pool_token_amount, 0, SwapError::WithdrawTooSmall);
+
+        // 提取手续费从 pool_token_amount 中扣除
+        let withdraw_fee = self.withdraw_fee(pool_token_amount)?;
+        let burn_pool_token_amount = pool_token_amount
+            .checked_sub(withdraw_fee)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        // 计算可提取的单币数量(换算成含息数量)
+        let (swap_token_amount, reserve_other) = self.swap.reserves(is_token_a);
+        let source_scale = interest_scale(&self.mint)?;
+        let other_scale = interest_scale(&self.other_mint)?;
+        let transfer_amount = self.swap.curve()?.withdraw_single_token_type_exact_in(
+            u128::from(self.swap.trade_fees), 
+            u128::from(burn_pool_token_amount), 
+            scale_interest_amount(swap_token_amount, source_scale, false)?, 
+            scale_interest_amount(reserve_other, other_scale, false)?, 
+            u128::from(self.pool_mint.supply)
+        ).ok_or(SwapError::ZeroTradingTokens)?;
+        let transfer_amount = unscale_interest_amount(transfer_amount, source_scale, false)?;
+        require_gt!(transfer_amount, 0, SwapError::ZeroTradingTokens);
+
+        // 用户扣除转账手续费后实际到账的数量
+        let destination_token_amount = amount_after_transfer_fee(transfer_amount, &self.mint)?;
+        if destination_token_amount < minimum_token_amount {
+            return err!(SwapError::ExceededSlippage);
+        }
+
+        self.settle(
+            remaining_accounts, 
+            invariant, 
+            is_token_a, 
+            pool_token_amount, 
+            withdraw_fee, 
+            transfer_amount, 
+            destination_token_amount
+        )
+    }
+
+    /// 两种模式共用的前置步骤：检查截止时间，以操作前的储备更新 TWAP 累计价格，
+    /// 返回操作前的不变量快照以及提取的是否为 token_a
+    fn prepare(&mut self, deadline: Option<i64>) -> Result<(PoolInvariant, bool)> {
+        check_deadline(deadline)?;
+
+        self.swap.update_price_oracle(&self.observations)?;
+        if self.mint.key() == self.swap.token_a_mint {
+            let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.mint, &self.other_mint)?;
+            Ok((invariant, true))
+        } else if self.mint.key() == self.swap.token_b_mint {
+            let invariant = PoolInvariant::load(&self.swap, &self.pool_mint, &self.other_mint, &self.mint)?;
+            Ok((invariant, false))
+        } else {
+            err!(SwapError::InvalidMint)
+        }
+    }
+
+    /// 提取手续费，以协议手续费账户提取时免收
+    fn withdraw_fee(&self, pool_token_amount: u64) -> Result<u64> {
+        if self.pool_fee_account.key() == self.user_mint_account.key() {
+            return Ok(0);
+        }
+        to_u64(calculation_fee(
+            u128::from(pool_token_amount), 
+            u128::from(self.swap.withdraw_fees)
+        ).ok_or(SwapError::FeeCalculationFailure)?)
+    }
+
+    /// 两种模式共用的结算：收取提取手续费、销毁池币、转出单币，发出事件后校验不变量。
+    /// `pool_token_amount` 为用户付出的池币总量(含提取手续费)
+    #[allow(clippy::too_many_arguments)]
+    fn settle(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        invariant: PoolInvariant,
+        is_token_a: bool,
+        pool_token_amount: u64,
+        withdraw_fee: u64,
+        transfer_amount: u64,
+        destination_token_amount: u64,
+    ) -> Result<()> {
         if pool_token_amount > self.user_mint_account.amount {
             return err!(SwapError::InsufficientPoolTokenBalance);
         }
+        let burn_pool_token_amount = pool_token_amount
+            .checked_sub(withdraw_fee)
+            .ok_or(SwapError::CalculationFailure)?;
+
+        let pool_token = if is_token_a {
+            self.swap.withdraw_reserves(transfer_amount, 0)?;
+            &self.token_a
+        } else {
+            self.swap.withdraw_reserves(0, transfer_amount)?;
+            &self.token_b
+        };
 
-        let withdraw_fee = to_u64(withdraw_fee)?;
         if withdraw_fee > 0 {
             transfer_tokens(
                 &self.user_mint_account, 
@@ -118,8 +275,9 @@ impl<'info> WithdrawSingle<'info> {
                 withdraw_fee, 
                 &self.pool_mint, 
                 self.user.to_account_info(), 
-                &self.token_program, 
-                None
+                &self.lp_token_program, 
+                None, 
+                remaining_accounts
             )?;
             msg!("提取手续费(提取单币种): {}", withdraw_fee);
         }
@@ -129,34 +287,48 @@ impl<'info> WithdrawSingle<'info> {
             &self.user_mint_account, 
             &self.pool_mint, 
             self.user.to_account_info(), 
-            &self.token_program, 
-            to_u64(burn_pool_token_amount)?
+            &self.lp_token_program, 
+            burn_pool_token_amount
         )?;
         msg!("销毁池币(单币种提取): {}", burn_pool_token_amount);
 
-        // 转账
-        transfer_tokens(
-            &self.pool_token, 
-            &self.user_token, 
-            destination_token_amount, 
+        // 用户 ATA 不存在时创建后转账
+        let user_token = UserTokenAccount::load_or_create(
+            self.user_token.to_account_info(), 
+            &self.user, 
+            &self.mint, 
+            &self.token_program, 
+            &self.system_program, 
+            &self.associated_token_program
+        )?;
+        transfer_token_infos(
+            pool_token.to_account_info(), 
+            user_token.to_account_info(), 
+            transfer_amount, 
             &self.mint, 
             self.swap.to_account_info(), 
             &self.token_program, 
             Some(&[&[
                 Swap::SWAP_SEEDS,
-                &[bump_swap]
-            ]])
+                self.swap.token_a_mint.as_ref(),
+                self.swap.token_b_mint.as_ref(),
+                &[self.swap.swap_bump_seed]
+            ]]), 
+            remaining_accounts
         )?;
-        msg!("转账(单币种提取): {}", destination_token_amount);
+        msg!("转账(单币种提取): {}", transfer_amount);
+
+        // wSOL 账户为本指令新建时解包给用户
+        unwrap_native(&self.user, &user_token, &self.token_program)?;
 
         emit!(WithdrawSingleEvent {
             user: self.user.key(),
             mint: self.mint.key(),
             pool_token_amount,
             destination_token_amount,
-            withdraw_fee: withdraw_fee,
+            withdraw_fee,
         });
 
-        Ok(())
+        invariant.check(&self.swap, &mut self.token_a, &mut self.token_b, &mut self.pool_mint)
     }
 }
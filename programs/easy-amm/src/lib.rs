@@ -16,82 +16,242 @@ pub mod easy_amm {
     use super::*;
 
     // 池子初始化
-    pub fn initialize_swap(
-        ctx: Context<InitializeSwap>,
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeSwap<'info>>,
         trade_fees: u16, 
         withdraw_fees: u16,
+        owner_trade_fees: u16,
+        host_fees: u16,
+        flash_fees: u16,
         amount_a: u64,
         amount_b: u64,
+        curve_type: u8,
+        amp_factor: u64,
+        lp_decimals: u8,
+        lp_metadata_uri: Option<String>,
     ) -> Result<()> {
-        ctx.accounts.process(trade_fees, withdraw_fees, amount_a, amount_b, ctx.bumps)
+        ctx.accounts.process(
+            ctx.remaining_accounts,
+            trade_fees, 
+            withdraw_fees, 
+            owner_trade_fees, 
+            host_fees, 
+            flash_fees, 
+            amount_a, 
+            amount_b, 
+            curve_type, 
+            amp_factor, 
+            lp_decimals, 
+            lp_metadata_uri, 
+            ctx.bumps
+        )
     }
 
     // 双币提取
-    pub fn withdraw_all(
-        ctx: Context<WithdrawAll>,
+    pub fn withdraw_all<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawAll<'info>>,
         token_amount: u64,
         minimum_token_a_amount: u64,
         minimum_token_b_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
         ctx.accounts.process(
+            ctx.remaining_accounts,
             token_amount, 
             minimum_token_a_amount, 
             minimum_token_b_amount, 
-            ctx.bumps.swap
+            deadline
         )
     }
 
     // 单币提取
-    pub fn withdraw_single(
-        ctx: Context<WithdrawSingle>,
+    pub fn withdraw_single<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawSingle<'info>>,
         destination_token_amount: u64,
         maximum_pool_token_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
         ctx.accounts.process(
-            ctx.bumps.swap, 
+            ctx.remaining_accounts,
             destination_token_amount, 
-            maximum_pool_token_amount
+            maximum_pool_token_amount,
+            deadline
+        )
+    }
+
+    // 精确输入单币提取：销毁指定数量的池币
+    pub fn withdraw_single_exact_in<'info>(
+        ctx: Context<'_, '_, '_, 'info, WithdrawSingle<'info>>,
+        pool_token_amount: u64,
+        minimum_token_amount: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        ctx.accounts.process_exact_in(
+            ctx.remaining_accounts,
+            pool_token_amount, 
+            minimum_token_amount,
+            deadline
         )
     }
 
     // 存入流动性(双币)
-    pub fn deposit(
-        ctx: Context<Deposit>,
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, '_, 'info, Deposit<'info>>,
         pool_token_amount: u64,
         maximum_token_a_amount: u64,
         maximum_token_b_amount: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
         ctx.accounts.process(
-            ctx.bumps.swap, 
+            ctx.remaining_accounts,
             pool_token_amount, 
             maximum_token_a_amount, 
-            maximum_token_b_amount
+            maximum_token_b_amount,
+            deadline
         )
     }
 
-    pub fn deposit_single(
-        ctx: Context<DepositSingle>,
+    pub fn deposit_single<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositSingle<'info>>,
         source_token_amount: u64,
-        minimum_pool_token_amount: u64
+        minimum_pool_token_amount: u64,
+        deadline: Option<i64>
     ) -> Result<()> {
         ctx.accounts.process(
-            ctx.bumps.swap, 
+            ctx.remaining_accounts,
             source_token_amount, 
-            minimum_pool_token_amount
+            minimum_pool_token_amount,
+            deadline
+        )
+    }
+
+    // 精确输出单币存入：铸造指定数量的池币
+    pub fn deposit_single_exact_out<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositSingle<'info>>,
+        pool_token_amount: u64,
+        maximum_source_token_amount: u64,
+        deadline: Option<i64>
+    ) -> Result<()> {
+        ctx.accounts.process_exact_out(
+            ctx.remaining_accounts,
+            pool_token_amount, 
+            maximum_source_token_amount,
+            deadline
         )
     }
 
-    pub fn exchange(
-        ctx: Context<Exchange>,
+    pub fn exchange<'info>(
+        ctx: Context<'_, '_, '_, 'info, Exchange<'info>>,
         a_to_b: bool,
         amount_in: u64,
-        minimum_amount_out: u64
+        minimum_amount_out: u64,
+        deadline: Option<i64>
     ) -> Result<()> {
         ctx.accounts.process(
-            ctx.bumps.swap, 
+            ctx.remaining_accounts,
             a_to_b, 
             amount_in, 
-            minimum_amount_out
+            minimum_amount_out,
+            deadline
+        )
+    }
+
+    // 精确输出兑换
+    pub fn exchange_exact_out<'info>(
+        ctx: Context<'_, '_, '_, 'info, Exchange<'info>>,
+        a_to_b: bool,
+        amount_out: u64,
+        maximum_amount_in: u64,
+        deadline: Option<i64>
+    ) -> Result<()> {
+        ctx.accounts.process_exact_out(
+            ctx.remaining_accounts,
+            a_to_b, 
+            amount_out, 
+            maximum_amount_in,
+            deadline
         )
     }
+
+    // 更新手续费(仅管理员)
+    pub fn update_fees(
+        ctx: Context<UpdateFees>,
+        trade_fees: u16,
+        withdraw_fees: u16,
+        owner_trade_fees: u16,
+        host_fees: u16,
+        flash_fees: u16
+    ) -> Result<()> {
+        ctx.accounts.process(trade_fees, withdraw_fees, owner_trade_fees, host_fees, flash_fees)
+    }
+
+    // 设置暂停标志(仅管理员)
+    pub fn set_pause(
+        ctx: Context<SetPause>,
+        paused: u8
+    ) -> Result<()> {
+        ctx.accounts.process(paused)
+    }
+
+    // 转移池子管理员(仅管理员)
+    pub fn set_admin(
+        ctx: Context<SetAdmin>,
+        new_admin: Pubkey
+    ) -> Result<()> {
+        ctx.accounts.process(new_admin)
+    }
+
+    // 扩大观测槽位数
+    pub fn increase_observation_cardinality(
+        ctx: Context<IncreaseObservationCardinality>,
+        cardinality_next: u16
+    ) -> Result<()> {
+        ctx.accounts.process(cardinality_next)
+    }
+
+    // 闪电贷，同一笔交易中之后必须调用 repay_flash_loan
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, '_, 'info, FlashLoan<'info>>,
+        amount_a: u64,
+        amount_b: u64
+    ) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts, amount_a, amount_b)
+    }
+
+    // 归还闪电贷
+    pub fn repay_flash_loan<'info>(ctx: Context<'_, '_, '_, 'info, RepayFlashLoan<'info>>) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts)
+    }
+
+    // 闪电兑换，先转出再回调调用方程序，remaining accounts 原样传给回调
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashSwap<'info>>,
+        a_to_b: bool,
+        amount_out: u64,
+        data: Vec<u8>
+    ) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts, a_to_b, amount_out, data)
+    }
+
+    // 多跳路由兑换，每一跳的账户按顺序放在 remaining accounts 中，之后为转账钩子所需的账户
+    pub fn route_swap<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RouteSwap<'info>>,
+        hops: u8,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        deadline: Option<i64>
+    ) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts, hops, amount_in, minimum_amount_out, deadline)
+    }
+
+    // 转出金库中超出储备的代币(仅管理员)
+    pub fn skim<'info>(ctx: Context<'_, '_, '_, 'info, Skim<'info>>) -> Result<()> {
+        ctx.accounts.process(ctx.remaining_accounts)
+    }
+
+    // 以金库余额重置储备(仅管理员)
+    pub fn sync(ctx: Context<Sync>) -> Result<()> {
+        ctx.accounts.process()
+    }
 }
@@ -1,9 +1,18 @@
 //! 池子全局账户
 
+use std::cell::RefMut;
+
 use anchor_lang::prelude::*;
+use spl_math::approximations::sqrt;
+
+use crate::{
+    error::SwapError,
+    shared::{ConstantProductCurve, CurveCalculator, StableCurve},
+};
 
 
 #[account]
+#[derive(Default)]
 pub struct Swap {
     pub token_a: Pubkey,
     pub token_b: Pubkey,
@@ -11,16 +20,39 @@ pub struct Swap {
     pub pool_mint: Pubkey,
     pub token_a_mint: Pubkey,
     pub token_b_mint: Pubkey,
+    pub admin: Pubkey,
     pub trade_fees: u16,
     pub withdraw_fees: u16,
     pub swap_bump_seed: u8,
     pub pool_mint_bump_seed: u8,
     pub token_a_bump_seed: u8,
     pub token_b_bump_seed: u8,
+    pub paused: u8,
+    pub curve_type: u8,
+    pub amp_factor: u64,
+    pub owner_trade_fees: u16,
+    pub host_fees: u16,
+    pub price_a_cumulative_last: u128,
+    pub price_b_cumulative_last: u128,
+    pub block_timestamp_last: i64,
+    pub flash_fees: u16,
+    /// 闪电贷进行中，归还前禁止其他改变余额的操作
+    pub flash_loan_locked: bool,
+    /// 只由 `flash_loan` 置位，`repay_flash_loan` 据此确认存在待归还的闪电贷，
+    /// 闪电兑换的回调无法借此解除锁定
+    pub flash_loan_active: bool,
+    /// 借出前的金库余额与应收闪电贷手续费，归还时据此校验
+    pub flash_loan_reserve_a: u64,
+    pub flash_loan_reserve_b: u64,
+    pub flash_loan_fee_a: u64,
+    pub flash_loan_fee_b: u64,
+    /// 池子记账的储备量，只由本程序的指令更新，直接转入金库的代币不计入
+    pub reserve_a: u64,
+    pub reserve_b: u64,
 }
 
 impl Swap {
-    pub const SWAP_SPACE: usize = 208;
+    pub const SWAP_SPACE: usize = 346;
     pub const SWAP_SEEDS: &'static [u8] = b"easy-amm";
     pub const TOKEN_A_SEEDS: &'static [u8] = b"token_a";
     pub const TOKEN_B_SEEDS: &'static [u8] = b"token_b";
@@ -28,8 +60,259 @@ impl Swap {
 
     pub const FEES_BASIS_POINTS: u64 = 10_000;
     pub const INITIAL_SWAP_POOL_AMOUNT: u64 = 1_000_000_000;
+    /// 初始 LP 中永久锁定在池子 PDA 名下的数量，池子因此无法被提取至空池
+    pub const MINIMUM_LIQUIDITY: u64 = 1_000;
     pub const MAX_TRADE_FEES: u16 = 5_000;
     pub const MAX_WITHDRAW_FEES: u16 = 5_000;
+    pub const MAX_HOST_FEES: u16 = 10_000;
+    pub const MAX_FLASH_FEES: u16 = 1_000;
 
     pub const MIN_TOKEN_AMOUNT: u64 = 1_000;
+
+    // 暂停标志位，可分别暂停存入、提取和兑换
+    pub const PAUSE_DEPOSIT: u8 = 1 << 0;
+    pub const PAUSE_WITHDRAW: u8 = 1 << 1;
+    pub const PAUSE_SWAP: u8 = 1 << 2;
+    pub const PAUSE_ALL: u8 = Self::PAUSE_DEPOSIT | Self::PAUSE_WITHDRAW | Self::PAUSE_SWAP;
+
+    // 定价曲线
+    pub const CURVE_CONSTANT_PRODUCT: u8 = 0;
+    pub const CURVE_STABLE: u8 = 1;
+    pub const MIN_AMP_FACTOR: u64 = 1;
+    pub const MAX_AMP_FACTOR: u64 = 1_000_000;
+
+    /// 校验手续费配置，交易手续费与协议手续费之和受 `MAX_TRADE_FEES` 限制，
+    /// `host_fees` 为协议手续费中分给推荐方的比例
+    pub fn validate_fees(
+        trade_fees: u16,
+        withdraw_fees: u16,
+        owner_trade_fees: u16,
+        host_fees: u16,
+        flash_fees: u16,
+    ) -> Result<()> {
+        require!(
+            u32::from(trade_fees) + u32::from(owner_trade_fees) <= u32::from(Self::MAX_TRADE_FEES),
+            SwapError::TradeFeeTooHigh
+        );
+        require!(withdraw_fees <= Self::MAX_WITHDRAW_FEES, SwapError::WithdrawFeeTooHigh);
+        require!(host_fees <= Self::MAX_HOST_FEES, SwapError::HostFeeTooHigh);
+        require!(flash_fees <= Self::MAX_FLASH_FEES, SwapError::FlashFeeTooHigh);
+        Ok(())
+    }
+
+    /// 兑换时收取的总手续费(LP 手续费 + 协议手续费)
+    pub fn total_trade_fees(&self) -> u128 {
+        u128::from(self.trade_fees) + u128::from(self.owner_trade_fees)
+    }
+
+    /// 以记账储备更新 TWAP 累计价格并写入观测点，需在改变储备之前调用。
+    ///
+    /// 价格为 Q64.64 定点数：`price_a` 为 1 个 token_a 值多少 token_b。
+    /// 与 Uniswap v2 一致，累计值允许溢出回绕，读取方用两次采样的差值
+    /// (`wrapping_sub`) 除以时间差得到区间均价。
+    pub fn update_price_oracle(&mut self, observations: &AccountLoader<Observations>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now.saturating_sub(self.block_timestamp_last);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+
+        if let Some((price_a, price_b)) = Self::spot_prices(self.reserve_a, self.reserve_b) {
+            let elapsed = elapsed as u128;
+            self.price_a_cumulative_last = self.price_a_cumulative_last
+                .wrapping_add(price_a.wrapping_mul(elapsed));
+            self.price_b_cumulative_last = self.price_b_cumulative_last
+                .wrapping_add(price_b.wrapping_mul(elapsed));
+        }
+        self.block_timestamp_last = now;
+
+        // 观测点记录的是上一观测点到现在这段时间的流动性，即改变前的储备
+        let liquidity = sqrt(
+            u128::from(self.reserve_a) * u128::from(self.reserve_b)
+        ).ok_or(SwapError::CalculationFailure)?;
+        let (mut header, mut slots) = Observations::load_mut(observations)?;
+        header.write(
+            &mut slots, 
+            now, 
+            self.price_a_cumulative_last, 
+            self.price_b_cumulative_last, 
+            liquidity
+        );
+
+        Ok(())
+    }
+
+    /// 当前瞬时价格 (price_a, price_b)，Q64.64 定点数，任一侧为空时返回 None
+    pub fn spot_prices(reserve_a: u64, reserve_b: u64) -> Option<(u128, u128)> {
+        if reserve_a == 0 || reserve_b == 0 {
+            return None;
+        }
+        Some((
+            (u128::from(reserve_b) << 64) / u128::from(reserve_a),
+            (u128::from(reserve_a) << 64) / u128::from(reserve_b),
+        ))
+    }
+
+    /// 记账：金库收到 `amount_a` / `amount_b`
+    pub fn deposit_reserves(&mut self, amount_a: u64, amount_b: u64) -> Result<()> {
+        self.reserve_a = self.reserve_a.checked_add(amount_a).ok_or(SwapError::CalculationFailure)?;
+        self.reserve_b = self.reserve_b.checked_add(amount_b).ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// 记账：金库转出 `amount_a` / `amount_b`
+    pub fn withdraw_reserves(&mut self, amount_a: u64, amount_b: u64) -> Result<()> {
+        self.reserve_a = self.reserve_a.checked_sub(amount_a).ok_or(SwapError::CalculationFailure)?;
+        self.reserve_b = self.reserve_b.checked_sub(amount_b).ok_or(SwapError::CalculationFailure)?;
+        Ok(())
+    }
+
+    /// 按兑换方向返回 (源代币储备, 目标代币储备)
+    pub fn reserves(&self, a_to_b: bool) -> (u64, u64) {
+        if a_to_b {
+            (self.reserve_a, self.reserve_b)
+        } else {
+            (self.reserve_b, self.reserve_a)
+        }
+    }
+
+    pub fn is_paused(&self, flag: u8) -> bool {
+        self.paused & flag != 0
+    }
+
+    /// 根据 `curve_type` 返回池子使用的定价曲线
+    pub fn curve(&self) -> Result<Box<dyn CurveCalculator>> {
+        match self.curve_type {
+            Self::CURVE_CONSTANT_PRODUCT => Ok(Box::new(ConstantProductCurve)),
+            Self::CURVE_STABLE => Ok(Box::new(StableCurve { amp_factor: self.amp_factor })),
+            _ => err!(SwapError::InvalidCurveType),
+        }
+    }
+}
+
+
+/// 价格观测点
+#[zero_copy]
+#[derive(Default)]
+pub struct Observation {
+    pub block_timestamp: i64,
+    pub _padding: [u8; 8],
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    /// Σ sqrt(reserve_a·reserve_b)·Δt
+    pub liquidity_cumulative: u128,
+}
+
+impl Observation {
+    pub fn is_initialized(&self) -> bool {
+        self.block_timestamp != 0
+    }
+}
+
+
+/// 池子的历史价格观测环形缓冲区。账户数据依次为判别符、本头部和
+/// `cardinality_next` 个观测槽位，槽位数可通过指令扩容
+#[account(zero_copy)]
+pub struct Observations {
+    pub swap: Pubkey,
+    /// 最新观测点下标
+    pub index: u16,
+    /// 当前启用的槽位数
+    pub cardinality: u16,
+    /// 已分配的槽位数，写满当前环后启用
+    pub cardinality_next: u16,
+    pub bump: u8,
+    pub _padding: [u8; 17],
+}
+
+impl Observations {
+    pub const HEADER_SPACE: usize = 8 + std::mem::size_of::<Observations>();
+    pub const OBSERVATION_SEEDS: &'static [u8] = b"observations";
+    pub const MAX_CARDINALITY: u16 = 1_024;
+
+    /// 容纳 `cardinality` 个槽位所需的账户大小
+    pub fn space(cardinality: u16) -> usize {
+        Self::HEADER_SPACE + usize::from(cardinality) * std::mem::size_of::<Observation>()
+    }
+
+    /// 同时借用账户的头部和其后的全部槽位
+    pub fn load_mut<'a>(
+        loader: &'a AccountLoader<'_, Observations>,
+    ) -> Result<(RefMut<'a, Observations>, RefMut<'a, [Observation]>)> {
+        let info: &AccountInfo = loader.as_ref();
+        let data = info.try_borrow_mut_data()?;
+        require!(
+            data.len() >= Self::HEADER_SPACE && data[..8] == *Self::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        Self::split(data)
+    }
+
+    /// 创建账户的指令中借用头部和槽位，判别符由 Anchor 在指令结束时写入
+    pub fn load_init<'a>(
+        loader: &'a AccountLoader<'_, Observations>,
+    ) -> Result<(RefMut<'a, Observations>, RefMut<'a, [Observation]>)> {
+        let info: &AccountInfo = loader.as_ref();
+        let data = info.try_borrow_mut_data()?;
+        require!(
+            data.len() >= Self::HEADER_SPACE && data[..8].iter().all(|byte| *byte == 0),
+            ErrorCode::AccountDiscriminatorAlreadySet
+        );
+        Self::split(data)
+    }
+
+    fn split<'a>(
+        data: RefMut<'a, &mut [u8]>,
+    ) -> Result<(RefMut<'a, Observations>, RefMut<'a, [Observation]>)> {
+        let (header, slots) = RefMut::map_split(data, |data| {
+            data[8..].split_at_mut(Self::HEADER_SPACE - 8)
+        });
+        let header = RefMut::filter_map(header, |header| bytemuck::try_from_bytes_mut(header).ok())
+            .map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+        let slots = RefMut::filter_map(slots, |slots| bytemuck::try_cast_slice_mut(slots).ok())
+            .map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+        Ok((header, slots))
+    }
+
+    pub fn latest<'a>(&self, slots: &'a [Observation]) -> &'a Observation {
+        &slots[self.index as usize]
+    }
+
+    /// 写入一个观测点，同一秒内只写一次。
+    /// `liquidity` 为上一观测点到现在这段时间内的流动性 sqrt(x·y)
+    pub fn write(
+        &mut self,
+        slots: &mut [Observation],
+        block_timestamp: i64,
+        price_a_cumulative: u128,
+        price_b_cumulative: u128,
+        liquidity: u128,
+    ) {
+        let last = *self.latest(slots);
+        if last.block_timestamp == block_timestamp {
+            return;
+        }
+
+        // 写到当前环的末尾时才扩展，保证环内观测点按时间有序
+        let cardinality = if self.cardinality_next > self.cardinality 
+            && self.index == self.cardinality - 1 
+        {
+            self.cardinality_next
+        } else {
+            self.cardinality
+        };
+        let index = (self.index + 1) % cardinality;
+
+        let elapsed = block_timestamp.saturating_sub(last.block_timestamp) as u128;
+        slots[index as usize] = Observation {
+            block_timestamp,
+            _padding: [0; 8],
+            price_a_cumulative,
+            price_b_cumulative,
+            liquidity_cumulative: last.liquidity_cumulative
+                .wrapping_add(liquidity.wrapping_mul(elapsed)),
+        };
+        self.index = index;
+        self.cardinality = cardinality;
+    }
 }
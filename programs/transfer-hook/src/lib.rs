@@ -0,0 +1,137 @@
+//! 测试用的最小转账钩子程序
+//! 每个 mint 一个计数器，记录钩子被调用的次数，用于验证池子转发 remaining accounts
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount};
+use spl_discriminator::SplDiscriminate;
+use spl_tlv_account_resolution::{
+    account::ExtraAccountMeta, 
+    seeds::Seed, 
+    state::ExtraAccountMetaList
+};
+use spl_transfer_hook_interface::instruction::ExecuteInstruction;
+
+declare_id!("ByitAu3NmnnGmG71Ptf93T7Z7JYC4dstn7PSMR8AzYQM");
+
+#[program]
+pub mod transfer_hook {
+    use super::*;
+
+    // 写入 ExtraAccountMetaList：每次转账额外需要该 mint 的计数器
+    pub fn initialize_extra_account_meta_list(
+        ctx: Context<InitializeExtraAccountMetaList>
+    ) -> Result<()> {
+        ExtraAccountMetaList::init::<ExecuteInstruction>(
+            &mut ctx.accounts.extra_account_meta_list.try_borrow_mut_data()?, 
+            &extra_account_metas()?
+        )?;
+        Ok(())
+    }
+
+    // 转账钩子：计数器加一
+    #[instruction(discriminator = ExecuteInstruction::SPL_DISCRIMINATOR_SLICE)]
+    pub fn transfer_hook(ctx: Context<TransferHook>, amount: u64) -> Result<()> {
+        ctx.accounts.counter.count += 1;
+        msg!("转账钩子: {}, 第 {} 次", amount, ctx.accounts.counter.count);
+        Ok(())
+    }
+}
+
+
+/// 钩子需要的额外账户：`[COUNTER_SEEDS, mint]` 派生的计数器，可写
+fn extra_account_metas() -> Result<Vec<ExtraAccountMeta>> {
+    Ok(vec![
+        ExtraAccountMeta::new_with_seeds(
+            &[
+                Seed::Literal { bytes: Counter::COUNTER_SEEDS.to_vec() },
+                Seed::AccountKey { index: 1 },
+            ], 
+            false, 
+            true
+        )?,
+    ])
+}
+
+
+#[derive(Accounts)]
+pub struct InitializeExtraAccountMetaList<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: ExtraAccountMetaList 账户，地址由转账钩子接口规定
+    #[account(
+        init,
+        payer = payer,
+        space = ExtraAccountMetaList::size_of(extra_account_metas()?.len())?,
+        seeds = [
+            b"extra-account-metas",
+            mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Counter::INIT_SPACE,
+        seeds = [
+            Counter::COUNTER_SEEDS,
+            mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub counter: Account<'info, Counter>,
+
+    pub system_program: Program<'info, System>,
+}
+
+
+/// 账户顺序由转账钩子接口规定：源账户、mint、目标账户、源账户所有者、
+/// ExtraAccountMetaList，之后是其中声明的额外账户
+#[derive(Accounts)]
+pub struct TransferHook<'info> {
+    #[account(token::mint = mint)]
+    pub source_token: InterfaceAccount<'info, TokenAccount>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(token::mint = mint)]
+    pub destination_token: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: 源账户所有者，可以是钱包或其他程序的 PDA
+    pub owner: UncheckedAccount<'info>,
+
+    /// CHECK: ExtraAccountMetaList 账户，只校验地址
+    #[account(
+        seeds = [
+            b"extra-account-metas",
+            mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub extra_account_meta_list: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            Counter::COUNTER_SEEDS,
+            mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub counter: Account<'info, Counter>,
+}
+
+
+#[account]
+#[derive(InitSpace)]
+pub struct Counter {
+    pub count: u64,
+}
+
+impl Counter {
+    pub const COUNTER_SEEDS: &'static [u8] = b"counter";
+}
@@ -3,8 +3,9 @@ import path from "path";
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { EasyAmm } from "../target/types/easy_amm";
-import { Keypair, LAMPORTS_PER_SOL, PublicKey } from "@solana/web3.js";
-import { getOrCreateAssociatedTokenAccount, createMint, mintTo, getAccount, getMint, getAssociatedTokenAddress } from "@solana/spl-token";
+import { TransferHook } from "../target/types/transfer_hook";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram, Transaction, TransactionInstruction, sendAndConfirmTransaction } from "@solana/web3.js";
+import { getOrCreateAssociatedTokenAccount, createMint, mintTo, getAccount, getMint, getAssociatedTokenAddress, createTransferInstruction, createWrappedNativeAccount, closeAccount, NATIVE_MINT, TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID, getAssociatedTokenAddressSync, getTokenMetadata, transfer, ExtensionType, getMintLen, createInitializeMintInstruction, createInitializeInterestBearingMintInstruction, createInitializeTransferFeeConfigInstruction, createInitializeTransferHookInstruction, getInterestBearingMintConfigState } from "@solana/spl-token";
 import { expect } from "chai";
 
 
@@ -13,6 +14,7 @@ describe("easy-amm", () => {
   anchor.setProvider(provider);
 
   const program = anchor.workspace.easyAmm as Program<EasyAmm>;
+  const hookProgram = anchor.workspace.transferHook as Program<TransferHook>;
   const connection = provider.connection;
 
   const payer = provider.wallet.publicKey;
@@ -73,9 +75,25 @@ describe("easy-amm", () => {
   // ---------- environment bootstrap ----------
   async function createEnvironment() {
     const user = Keypair.generate();
+    // airdrop SOL to user
+    const airdropSig = await connection.requestAirdrop(user.publicKey, 2 * LAMPORTS_PER_SOL);
+    const bh = await connection.getLatestBlockhash("confirmed");
+    await connection.confirmTransaction(
+      { signature: airdropSig, blockhash: bh.blockhash, lastValidBlockHeight: bh.lastValidBlockHeight },
+      "confirmed"
+    );
+
+    // create mints (6 decimals)
+    mintA = await createMint(connection, user, user.publicKey, null, 6);
+    mintB = await createMint(connection, user, user.publicKey, null, 6);
+    // 池子按 mint 对派生，token_a_mint 必须排在 token_b_mint 之前
+    if (Buffer.compare(mintA.toBuffer(), mintB.toBuffer()) > 0) {
+      [mintA, mintB] = [mintB, mintA];
+    }
+
     // derive PDAs
     [swapPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("easy-amm")],
+      [Buffer.from("easy-amm"), mintA.toBuffer(), mintB.toBuffer()],
       program.programId
     );
     [tokenAPda] = PublicKey.findProgramAddressSync(
@@ -91,17 +109,6 @@ describe("easy-amm", () => {
       program.programId
     );
 
-    // airdrop SOL to user
-    const airdropSig = await connection.requestAirdrop(user.publicKey, 2 * LAMPORTS_PER_SOL);
-    const bh = await connection.getLatestBlockhash("confirmed");
-    await connection.confirmTransaction(
-      { signature: airdropSig, blockhash: bh.blockhash, lastValidBlockHeight: bh.lastValidBlockHeight },
-      "confirmed"
-    );
-
-    // create mints (6 decimals)
-    mintA = await createMint(connection, user, user.publicKey, null, 6);
-    mintB = await createMint(connection, user, user.publicKey, null, 6);
 
     // user ATAs
     userTokenA = (await getOrCreateAssociatedTokenAccount(connection, user, mintA, user.publicKey)).address;
@@ -127,6 +134,118 @@ describe("easy-amm", () => {
   }
 
 
+  // ---------- Token-2022 / pool helpers ----------
+  // 创建 Token-2022 mint，`extensionIxs` 为 InitializeMint 之前执行的扩展初始化指令
+  async function createMint2022(
+    authority: Keypair,
+    extensions: ExtensionType[],
+    extensionIxs: (mint: PublicKey) => TransactionInstruction[] = () => [],
+    decimals = 6
+  ): Promise<PublicKey> {
+    const mint = Keypair.generate();
+    const space = getMintLen(extensions);
+    const tx = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: authority.publicKey,
+        newAccountPubkey: mint.publicKey,
+        space,
+        lamports: await connection.getMinimumBalanceForRentExemption(space),
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      ...extensionIxs(mint.publicKey),
+      createInitializeMintInstruction(mint.publicKey, decimals, authority.publicKey, null, TOKEN_2022_PROGRAM_ID)
+    );
+    await sendAndConfirmTransaction(connection, tx, [authority, mint]);
+    return mint.publicKey;
+  }
+
+  // 为 user 创建 ATA 并铸造 amount
+  async function fundUser(user: Keypair, mint: PublicKey, amount: number, tokenProgram: PublicKey): Promise<PublicKey> {
+    const ata = (await getOrCreateAssociatedTokenAccount(
+      connection, user, mint, user.publicKey, false, "confirmed", undefined, tokenProgram
+    )).address;
+    await mintTo(connection, user, mint, ata, user, amount, [], undefined, tokenProgram);
+    return ata;
+  }
+
+  // 按地址排序后以 user 的余额创建池子，LP 与两侧代币使用同一个 token 程序
+  async function createPool(
+    user: Keypair,
+    mintX: PublicKey,
+    mintY: PublicKey,
+    amount: number,
+    curveType: number,
+    ampFactor: number,
+    tokenProgram: PublicKey,
+    remainingAccounts: anchor.web3.AccountMeta[] = []
+  ) {
+    const [tokenAMint, tokenBMint] = Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0
+      ? [mintX, mintY]
+      : [mintY, mintX];
+    const [swap] = PublicKey.findProgramAddressSync(
+      [Buffer.from("easy-amm"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
+      program.programId
+    );
+    const [tokenA] = PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("token_a")], program.programId);
+    const [tokenB] = PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("token_b")], program.programId);
+    const [lpMint] = PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("lp_mint")], program.programId);
+
+    await program.methods.initializeSwap(
+      200, 300, 0, 0, 0,
+      new anchor.BN(amount),
+      new anchor.BN(amount),
+      curveType,
+      new anchor.BN(ampFactor),
+      6,
+      null
+    ).accounts({
+      payer: payer,
+      user: user.publicKey,
+      tokenAMint,
+      tokenBMint,
+      tokenProgram,
+      lpTokenProgram: tokenProgram
+    }).remainingAccounts(remainingAccounts).signers([user]).rpc();
+
+    return {
+      swap,
+      tokenAMint,
+      tokenBMint,
+      tokenA,
+      tokenB,
+      lpMint,
+      poolFeeAccount: getAssociatedTokenAddressSync(lpMint, payer, false, tokenProgram),
+    };
+  }
+
+
+  // route_swap 每一跳在 remaining accounts 中的 8 个账户
+  function routeHop(
+    swap: PublicKey,
+    source: PublicKey,
+    destination: PublicKey,
+    sourceMint: PublicKey,
+    destinationMint: PublicKey,
+    lpMint: PublicKey,
+    feeAccount: PublicKey
+  ): anchor.web3.AccountMeta[] {
+    return [
+      { pubkey: swap, isSigner: false, isWritable: true },
+      { pubkey: source, isSigner: false, isWritable: true },
+      { pubkey: destination, isSigner: false, isWritable: true },
+      { pubkey: sourceMint, isSigner: false, isWritable: false },
+      { pubkey: destinationMint, isSigner: false, isWritable: false },
+      { pubkey: lpMint, isSigner: false, isWritable: true },
+      { pubkey: feeAccount, isSigner: false, isWritable: true },
+      {
+        pubkey: PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("observations")], program.programId)[0],
+        isSigner: false,
+        isWritable: true
+      },
+    ];
+  }
+
+
   before(async () => {
     const cached = loadCache();
     if (cached) {
@@ -152,14 +271,22 @@ describe("easy-amm", () => {
     const tx = await program.methods.initializeSwap(
       200,
       300,
+      0,                    // 协议手续费
+      0,                    // 推荐方分成
+      0,                    // 闪电贷手续费
       new anchor.BN(100_000_000),
-      new anchor.BN(50_000_000)
+      new anchor.BN(50_000_000),
+      0,                    // 恒定乘积曲线
+      new anchor.BN(0),
+      6,                    // LP 精度
+      null                  // 不创建 LP 元数据
     ).accounts({
       payer: payer,
       user: user.publicKey,
       tokenAMint: mintA,
       tokenBMint: mintB,
-      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
     }).signers([user]).rpc();
     
     // …前面已经发送 initializeSwap 交易
@@ -181,12 +308,16 @@ describe("easy-amm", () => {
     expect(poolMintInfo.supply).to.equal(BigInt(1_000_000_000)); // 1000 * 10^6
     
     //--------------------------------------------------------------------
-    // 3. 计算 destination (user 的 LP ATA)，验证余额是否为 1000
+    // 3. 计算 destination (user 的 LP ATA)，验证余额为 1000 减去永久锁定的 MINIMUM_LIQUIDITY
     //--------------------------------------------------------------------
+    const MINIMUM_LIQUIDITY = BigInt(1_000);
     const destination = await getAssociatedTokenAddress(poolMint, user.publicKey);
     const destAccountInfo = await getAccount(connection, destination);
     
-    expect(destAccountInfo.amount).to.equal(BigInt(1_000_000_000));
+    expect(destAccountInfo.amount).to.equal(BigInt(1_000_000_000) - MINIMUM_LIQUIDITY);
+
+    const lockedAccount = await getAssociatedTokenAddress(poolMint, swapPda, true);
+    expect((await getAccount(connection, lockedAccount)).amount).to.equal(MINIMUM_LIQUIDITY);
     
     //--------------------------------------------------------------------
     // 4. 额外可验证：swap 账户里的配置是否写入正确
@@ -194,11 +325,284 @@ describe("easy-amm", () => {
     const swapAccount = await program.account.swap.fetch(swapPda);
     expect(swapAccount.tradeFees).to.equal(200);
     expect(swapAccount.withdrawFees).to.equal(300);
+    expect(swapAccount.admin.toBase58()).to.equal(payer.toBase58());
+    expect(swapAccount.blockTimestampLast.toNumber()).to.be.greaterThan(0);
     
     console.log("✅ 所有断言通过！");
     console.log("Your transaction signature", tx);
   });
 
+  it("Is second pool independent", async () => {
+    const user = loadUser();
+    const tokenProgram = anchor.utils.token.TOKEN_PROGRAM_ID;
+    const mintC = await createMint(connection, user, user.publicKey, null, 6);
+    const mintD = await createMint(connection, user, user.publicKey, null, 6);
+    await fundUser(user, mintC, 10_000_000_000, tokenProgram);
+    await fundUser(user, mintD, 10_000_000_000, tokenProgram);
+
+    const mainBefore = await program.account.swap.fetch(swapPda);
+    const mainTokABefore = (await getAccount(connection, tokenAPda)).amount;
+    const mainTokBBefore = (await getAccount(connection, tokenBPda)).amount;
+    const mainSupplyBefore = (await getMint(connection, poolMint)).supply;
+
+    // 不同币对的池子使用各自的 PDA、金库与 LP mint
+    const pool = await createPool(user, mintC, mintD, 1_000_000_000, 0, 0, tokenProgram);
+    expect(pool.swap.equals(swapPda)).to.be.false;
+    expect(pool.lpMint.equals(poolMint)).to.be.false;
+    const second = await program.account.swap.fetch(pool.swap);
+    expect(second.tokenAMint.equals(pool.tokenAMint)).to.be.true;
+    expect(second.tokenBMint.equals(pool.tokenBMint)).to.be.true;
+    expect(second.poolMint.equals(pool.lpMint)).to.be.true;
+
+    const tx = await program.methods.exchange(true, new anchor.BN(100_000_000), new anchor.BN(1), null)
+      .accounts({
+        user: user.publicKey,
+        swap: pool.swap,
+        tokenAMint: pool.tokenAMint,
+        tokenBMint: pool.tokenBMint,
+        poolFeeAccount: pool.poolFeeAccount,
+        tokenProgram,
+        lpTokenProgram: tokenProgram
+      }).signers([user]).rpc();
+    const secondAfter = await program.account.swap.fetch(pool.swap);
+    expect(secondAfter.reserveA.gt(second.reserveA)).to.be.true;
+    expect(secondAfter.reserveB.lt(second.reserveB)).to.be.true;
+
+    // 第一个池子的储备、金库余额与 LP 供应量不受影响
+    const mainAfter = await program.account.swap.fetch(swapPda);
+    expect(mainAfter.reserveA.eq(mainBefore.reserveA)).to.be.true;
+    expect(mainAfter.reserveB.eq(mainBefore.reserveB)).to.be.true;
+    expect((await getAccount(connection, tokenAPda)).amount).to.equal(mainTokABefore);
+    expect((await getAccount(connection, tokenBPda)).amount).to.equal(mainTokBBefore);
+    expect((await getMint(connection, poolMint)).supply).to.equal(mainSupplyBefore);
+
+    // 用第二个池子的金库冒充第一个池子的金库会被 PDA 约束拒绝
+    let rejected = false;
+    try {
+      await program.methods.exchange(true, new anchor.BN(1_000), new anchor.BN(1), null)
+        .accountsPartial({
+          user: user.publicKey,
+          swap: swapPda,
+          tokenAMint: mintA,
+          tokenBMint: mintB,
+          tokenA: pool.tokenA,
+          tokenB: pool.tokenB,
+          poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+          tokenProgram,
+          lpTokenProgram: tokenProgram
+        }).signers([user]).rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    expect(rejected).to.be.true;
+
+    console.log("✅ Second-Pool 校验通过 Tx:", tx);
+  });
+
+  it("Rejects mint with freeze authority", async () => {
+    const user = loadUser();
+    const frozenMint = await createMint(connection, user, user.publicKey, user.publicKey, 6);
+    const [tokenAMint, tokenBMint] = Buffer.compare(mintA.toBuffer(), frozenMint.toBuffer()) < 0
+      ? [mintA, frozenMint]
+      : [frozenMint, mintA];
+
+    let errorCode = "";
+    try {
+      await program.methods.initializeSwap(
+        200, 300, 0, 0, 0,
+        new anchor.BN(1_000_000),
+        new anchor.BN(1_000_000),
+        0,
+        new anchor.BN(0),
+        6,
+        null
+      ).accounts({
+        payer: payer,
+        user: user.publicKey,
+        tokenAMint,
+        tokenBMint,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("MintHasFreezeAuthority");
+  });
+
+  it("Is pool creation with a pre-funded LP mint address", async () => {
+    const user = loadUser();
+    const tokenProgram = anchor.utils.token.TOKEN_PROGRAM_ID;
+    const mintX = await createMint(connection, user, user.publicKey, null, 6);
+    const mintY = await createMint(connection, user, user.publicKey, null, 6);
+    await fundUser(user, mintX, 1_000_000_000, tokenProgram);
+    await fundUser(user, mintY, 1_000_000_000, tokenProgram);
+    const [tokenAMint, tokenBMint] = Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0
+      ? [mintX, mintY]
+      : [mintY, mintX];
+    const [swap] = PublicKey.findProgramAddressSync(
+      [Buffer.from("easy-amm"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
+      program.programId
+    );
+    const [lpMint] = PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("lp_mint")], program.programId);
+
+    // 任何人都可以向 LP mint 地址预先转入 lamports，建池不能因此失败
+    await sendAndConfirmTransaction(connection, new Transaction().add(
+      SystemProgram.transfer({ fromPubkey: user.publicKey, toPubkey: lpMint, lamports: 1_000_000 })
+    ), [user]);
+
+    const pool = await createPool(user, mintX, mintY, 100_000_000, 0, 0, tokenProgram);
+    expect(pool.lpMint.equals(lpMint)).to.be.true;
+    const lpMintInfo = await connection.getAccountInfo(lpMint);
+    expect(lpMintInfo.owner.equals(tokenProgram)).to.be.true;
+    expect(lpMintInfo.lamports).to.equal(await connection.getMinimumBalanceForRentExemption(lpMintInfo.data.length));
+    expect((await getMint(connection, lpMint)).mintAuthority.equals(swap)).to.be.true;
+  });
+
+  const lpMetadataUri = "https://example.com/lp.json";
+
+  it("Rejects LP metadata without Token-2022", async () => {
+    const user = loadUser();
+    const mintX = await createMint(connection, user, user.publicKey, null, 6);
+    const mintY = await createMint(connection, user, user.publicKey, null, 6);
+    const [tokenAMint, tokenBMint] = Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0
+      ? [mintX, mintY]
+      : [mintY, mintX];
+
+    let errorCode = "";
+    try {
+      await program.methods.initializeSwap(
+        200, 300, 0, 0, 0,
+        new anchor.BN(1_000_000),
+        new anchor.BN(1_000_000),
+        0,
+        new anchor.BN(0),
+        9,
+        lpMetadataUri         // SPL Token 不支持元数据扩展
+      ).accounts({
+        payer: payer,
+        user: user.publicKey,
+        tokenAMint,
+        tokenBMint,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("LpMetadataRequiresToken2022");
+  });
+
+  it("Is LP metadata on a Token-2022 LP mint", async () => {
+    const user = loadUser();
+    const mintX = await createMint(connection, user, user.publicKey, null, 6);
+    const mintY = await createMint(connection, user, user.publicKey, null, 6);
+    const [tokenAMint, tokenBMint] = Buffer.compare(mintX.toBuffer(), mintY.toBuffer()) < 0
+      ? [mintX, mintY]
+      : [mintY, mintX];
+    for (const mint of [tokenAMint, tokenBMint]) {
+      const ata = await getOrCreateAssociatedTokenAccount(connection, user, mint, user.publicKey);
+      await mintTo(connection, user, mint, ata.address, user, 100_000_000);
+    }
+    const [swap] = PublicKey.findProgramAddressSync(
+      [Buffer.from("easy-amm"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
+      program.programId
+    );
+    const [lpMint] = PublicKey.findProgramAddressSync([swap.toBuffer(), Buffer.from("lp_mint")], program.programId);
+
+    // 两侧代币使用 SPL Token，LP mint 使用 Token-2022
+    const initialize = (uri: string) => program.methods.initializeSwap(
+      200, 300, 0, 0, 0,
+      new anchor.BN(1_000_000),
+      new anchor.BN(1_000_000),
+      0,
+      new anchor.BN(0),
+      9,
+      uri
+    ).accounts({
+      payer: payer,
+      user: user.publicKey,
+      tokenAMint,
+      tokenBMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      lpTokenProgram: TOKEN_2022_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    // uri 超过 200 字节时拒绝
+    let errorCode = "";
+    try {
+      await initialize("x".repeat(201));
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("InvalidLpMetadata");
+
+    // 名称与符号由两侧代币生成，SPL Token 没有元数据符号时取 mint 地址的前 4 个字符
+    await initialize(lpMetadataUri);
+    const metadata = await getTokenMetadata(connection, lpMint, "confirmed", TOKEN_2022_PROGRAM_ID);
+    const [symbolA, symbolB] = [tokenAMint, tokenBMint].map((mint) => mint.toBase58().slice(0, 4));
+    expect(metadata.name).to.equal(`easy-amm ${symbolA}-${symbolB} LP`);
+    expect(metadata.symbol).to.equal(`${symbolA}-${symbolB}-LP`);
+    expect(metadata.uri).to.equal(lpMetadataUri);
+    expect(metadata.updateAuthority.toBase58()).to.equal(swap.toBase58());
+
+    // LP ATA 与 LP 铸造都走 lpTokenProgram
+    const userLp = getAssociatedTokenAddressSync(lpMint, user.publicKey, false, TOKEN_2022_PROGRAM_ID);
+    const oldUserLp = (await getAccount(connection, userLp, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+    await program.methods.deposit(
+      new anchor.BN(1_000_000),
+      new anchor.BN(10_000),
+      new anchor.BN(10_000),
+      null                  // deadline
+    ).accounts({
+      user: user.publicKey,
+      swap,
+      tokenAMint,
+      tokenBMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      lpTokenProgram: TOKEN_2022_PROGRAM_ID
+    }).signers([user]).rpc();
+    const newUserLp = (await getAccount(connection, userLp, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+    expect((newUserLp - oldUserLp).toString()).to.equal("1000000");
+  });
+
+  it("Is update fees", async () => {
+    const user = loadUser();
+
+    // 非管理员无法修改手续费
+    let failed = false;
+    try {
+      await program.methods.updateFees(100, 100, 0, 0, 0).accounts({
+        admin: user.publicKey,
+        swap: swapPda,
+      }).signers([user]).rpc();
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+
+    // 管理员先修改再恢复，后续用例依赖 200 / 300 的费率
+    await program.methods.updateFees(100, 150, 50, 2_000, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+    let swapAccount = await program.account.swap.fetch(swapPda);
+    expect(swapAccount.tradeFees).to.equal(100);
+    expect(swapAccount.withdrawFees).to.equal(150);
+    expect(swapAccount.ownerTradeFees).to.equal(50);
+    expect(swapAccount.hostFees).to.equal(2_000);
+
+    const tx = await program.methods.updateFees(200, 300, 0, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+    swapAccount = await program.account.swap.fetch(swapPda);
+    expect(swapAccount.tradeFees).to.equal(200);
+    expect(swapAccount.withdrawFees).to.equal(300);
+
+    console.log("✅ Update-Fees 校验通过 Tx:", tx);
+  });
+
   it("Is deposit", async () => {
     const user = loadUser();
     const OlduserLpAta = await getAssociatedTokenAddress(poolMint, user.publicKey);
@@ -225,12 +629,15 @@ describe("easy-amm", () => {
     const tx = await program.methods.deposit(
       new anchor.BN(poolTokenAmount.toString()),           // 想要 2_000 LP
       new anchor.BN(maxTokenA.toString()), 
-      new anchor.BN(maxTokenB.toString())
+      new anchor.BN(maxTokenB.toString()),
+      null                  // deadline
     ).accounts({
       user: user.publicKey,
+      swap: swapPda,
       tokenAMint: mintA,
       tokenBMint: mintB,
-      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
     }).signers([user]).rpc();
 
     // 校验
@@ -297,13 +704,16 @@ describe("easy-amm", () => {
     const tx = await program.methods.withdrawAll(
       new anchor.BN(poolTokenAmount.toString()),           // 想要 2_000 LP
       new anchor.BN(minTokenA.toString()), 
-      new anchor.BN(minTokenB.toString())
+      new anchor.BN(minTokenB.toString()),
+      null                  // deadline
     ).accounts({
       user: user.publicKey,
+      swap: swapPda,
       tokenAMint: mintA,
       tokenBMint: mintB,
       poolFeeAccount,
-      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
     }).signers([user]).rpc();
 
     // 校验
@@ -384,12 +794,14 @@ describe("easy-amm", () => {
     //--------------------------------------------------------------------
     const tx = await program.methods.depositSingle(
       new anchor.BN(sourceTokenAmount.toString()),
-      new anchor.BN(minPoolTokenAmount.toString())
+      new anchor.BN(minPoolTokenAmount.toString()),
+      null                  // deadline
     ).accounts({
       user: user.publicKey,
-      poolToken: tokenAPda,
       mint: mintA,
+      otherMint: mintB,
       tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
     }).signers([user]).rpc();
 
     //--------------------------------------------------------------------
@@ -428,6 +840,66 @@ describe("easy-amm", () => {
     console.log("✅ Deposit-Single 校验通过 Tx:", tx);
   });
 
+  it("Is deposit single exact out", async () => {
+    const user = loadUser();
+    const userLpATA = await getAssociatedTokenAddress(poolMint, user.publicKey);
+    const oldUserLp = (await getAccount(connection, userLpATA)).amount;
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const reserveA = (await getAccount(connection, tokenAPda)).amount;
+    const poolSupply = (await getMint(connection, poolMint)).supply;
+
+    // ΔA = A * ((1 + ΔL / L)^2 - 1)，其中一半按 2% 交易手续费反推
+    const poolTokenAmount = BigInt(10_000_000);
+    const base = 1 + Number(poolTokenAmount) / Number(poolSupply);
+    const net = Math.ceil(Number(reserveA) * (base * base - 1));
+    const half = Math.ceil(net / 2);
+    const expectedIn = net - half + Math.ceil(half * 10_000 / 9_800);
+
+    // 需要存入的数量超过上限时失败
+    try {
+      await program.methods.depositSingleExactOut(
+        new anchor.BN(poolTokenAmount.toString()),
+        new anchor.BN(expectedIn - 10),
+        null
+      ).accounts({
+        user: user.publicKey,
+        mint: mintA,
+        otherMint: mintB,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      }).signers([user]).rpc();
+      expect.fail("should exceed slippage");
+    } catch (err) {
+      expect(err.error.errorCode.code).to.equal("ExceededSlippage");
+    }
+
+    const tx = await program.methods.depositSingleExactOut(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(expectedIn + 2),
+      null
+    ).accounts({
+      user: user.publicKey,
+      mint: mintA,
+      otherMint: mintB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+    }).signers([user]).rpc();
+
+    const userLpAfter = (await getAccount(connection, userLpATA)).amount;
+    const userTokAAfter = (await getAccount(connection, userTokenA)).amount;
+    const poolSupplyAfter = (await getMint(connection, poolMint)).supply;
+
+    // 精确铸造 poolTokenAmount
+    expect(userLpAfter - oldUserLp).to.equal(poolTokenAmount);
+    expect(poolSupplyAfter - poolSupply).to.equal(poolTokenAmount);
+
+    // 支付的 tokenA 与预估一致（容差 ±2）
+    const paid = Number(oldUserTokA - userTokAAfter);
+    expect(Math.abs(paid - expectedIn) <= 2).to.be.true;
+
+    console.log("✅ Deposit-Single-Exact-Out 校验通过 Tx:", tx);
+  });
+
   it("Is withdraw single", async () => {
     const user = loadUser();
     const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
@@ -479,13 +951,16 @@ describe("easy-amm", () => {
     //--------------------------------------------------------------------
     const tx = await program.methods.withdrawSingle(
         new anchor.BN(destTokenAmount.toString()),
-        new anchor.BN(maxPoolTokenBurn.toString())
+        new anchor.BN(maxPoolTokenBurn.toString()),
+        null
       ).accounts({
         user: user.publicKey,
-        poolToken: tokenAPda,
-        mint: mintA,
+        swap: swapPda,
+          mint: mintA,
+        otherMint: mintB,
         poolFeeAccount,
-        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
       }).signers([user]).rpc();
   
     //--------------------------------------------------------------------
@@ -520,6 +995,331 @@ describe("easy-amm", () => {
     console.log("✅ Withdraw-Single 校验通过 Tx:", tx);
   });
 
+  it("Is withdraw single exact in", async () => {
+    const user = loadUser();
+    const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
+    const userLpATA = await getAssociatedTokenAddress(poolMint, user.publicKey);
+    const oldUserLp = (await getAccount(connection, userLpATA)).amount;
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const reserveA = (await getAccount(connection, tokenAPda)).amount;
+    const poolSupply = (await getMint(connection, poolMint)).supply;
+
+    // 销毁的 LP 中先扣除 3% 提取手续费，剩余部分按曲线换成 tokenA，其中一半收取 2% 交易手续费
+    const poolTokenAmount = BigInt(10_000_000);
+    const withdrawFee = poolTokenAmount * BigInt(300) / BigInt(10_000);
+    const burnLP = poolTokenAmount - withdrawFee;
+    const base = 1 - Number(burnLP) / Number(poolSupply);
+    const gross = Math.floor(Number(reserveA) * (1 - base * base));
+    // 净额为毛额 · 2(1 − f) / (2 − f)，与 exact out 的折算互逆
+    const expectedOut = Math.floor(gross * 2 * (10_000 - 200) / (20_000 - 200));
+
+    // 滑点下限高于可提取数量时失败
+    try {
+      await program.methods.withdrawSingleExactIn(
+        new anchor.BN(poolTokenAmount.toString()),
+        new anchor.BN(expectedOut + 10),
+        null
+      ).accounts({
+        user: user.publicKey,
+        swap: swapPda,
+        mint: mintA,
+        otherMint: mintB,
+        poolFeeAccount,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+      expect.fail("should exceed slippage");
+    } catch (err) {
+      expect(err.error.errorCode.code).to.equal("ExceededSlippage");
+    }
+
+    const tx = await program.methods.withdrawSingleExactIn(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(expectedOut - 2),
+      null
+    ).accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      mint: mintA,
+      otherMint: mintB,
+      poolFeeAccount,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    const userLpAfter = (await getAccount(connection, userLpATA)).amount;
+    const userTokAAfter = (await getAccount(connection, userTokenA)).amount;
+    const poolSupplyAfter = (await getMint(connection, poolMint)).supply;
+
+    // 用户 LP 精确减少 poolTokenAmount，其中 burnLP 被销毁
+    expect(oldUserLp - userLpAfter).to.equal(poolTokenAmount);
+    expect(poolSupply - poolSupplyAfter).to.equal(burnLP);
+
+    // 到账 tokenA 与预估一致（容差 ±2）
+    const received = Number(userTokAAfter - oldUserTokA);
+    expect(Math.abs(received - expectedOut) <= 2).to.be.true;
+
+    console.log("✅ Withdraw-Single-Exact-In 校验通过 Tx:", tx);
+  });
+
+  it("Is set pause", async () => {
+    const user = loadUser();
+    const PAUSE_SWAP = 1 << 2;
+
+    // 非管理员无法暂停
+    let failed = false;
+    try {
+      await program.methods.setPause(PAUSE_SWAP).accounts({
+        admin: user.publicKey,
+        swap: swapPda,
+      }).signers([user]).rpc();
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+
+    // 仅暂停兑换
+    await program.methods.setPause(PAUSE_SWAP).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+    expect((await program.account.swap.fetch(swapPda)).paused).to.equal(PAUSE_SWAP);
+
+    let errorCode = "";
+    try {
+      await program.methods.exchange(
+        true,
+        new anchor.BN(1_000_000),
+        new anchor.BN(0),
+        null
+      ).accounts({
+        user: user.publicKey,
+        swap: swapPda,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("PoolPaused");
+
+    // 恢复
+    const tx = await program.methods.setPause(0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+    expect((await program.account.swap.fetch(swapPda)).paused).to.equal(0);
+
+    console.log("✅ Set-Pause 校验通过 Tx:", tx);
+  });
+
+  it("Is set admin", async () => {
+    const user = loadUser();
+    const newAdmin = Keypair.generate();
+
+    // 非管理员无法转移管理员
+    let errorCode = "";
+    try {
+      await program.methods.setAdmin(user.publicKey).accounts({
+        admin: user.publicKey,
+        swap: swapPda,
+      }).signers([user]).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("Unauthorized");
+
+    // 不能转移给空地址
+    errorCode = "";
+    try {
+      await program.methods.setAdmin(PublicKey.default).accounts({
+        admin: payer,
+        swap: swapPda,
+      }).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("InvalidAdmin");
+
+    await program.methods.setAdmin(newAdmin.publicKey).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+    expect((await program.account.swap.fetch(swapPda)).admin.toBase58()).to.equal(newAdmin.publicKey.toBase58());
+
+    // 原管理员失去权限，新管理员可以执行管理指令
+    errorCode = "";
+    try {
+      await program.methods.setPause(0).accounts({
+        admin: payer,
+        swap: swapPda,
+      }).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("Unauthorized");
+
+    await program.methods.setPause(0).accounts({
+      admin: newAdmin.publicKey,
+      swap: swapPda,
+    }).signers([newAdmin]).rpc();
+
+    // 转回 payer，后续用例依赖 payer 为管理员
+    const tx = await program.methods.setAdmin(payer).accounts({
+      admin: newAdmin.publicKey,
+      swap: swapPda,
+    }).signers([newAdmin]).rpc();
+    expect((await program.account.swap.fetch(swapPda)).admin.toBase58()).to.equal(payer.toBase58());
+
+    console.log("✅ Set-Admin 校验通过 Tx:", tx);
+  });
+
+  it("Is skim and sync", async () => {
+    const user = loadUser();
+    const donation = BigInt(1_000_000);
+
+    // 直接转入金库的代币不计入储备
+    await transfer(connection, user, userTokenA, tokenAPda, user, donation);
+    const reserveA = BigInt((await program.account.swap.fetch(swapPda)).reserveA.toString());
+    expect((await getAccount(connection, tokenAPda)).amount).to.equal(reserveA + donation);
+
+    // 管理员把多余部分转给指定账户
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    await program.methods.skim().accounts({
+      admin: payer,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      recipientTokenA: userTokenA,
+      recipientTokenB: userTokenB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).rpc();
+    expect((await getAccount(connection, userTokenA)).amount).to.equal(oldUserTokA + donation);
+    expect((await getAccount(connection, tokenAPda)).amount).to.equal(reserveA);
+
+    // 再次转入后 sync，储备采用金库余额
+    await transfer(connection, user, userTokenA, tokenAPda, user, donation);
+    const tx = await program.methods.sync().accounts({
+      admin: payer,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+    }).rpc();
+    expect((await program.account.swap.fetch(swapPda)).reserveA.toString()).to.equal((reserveA + donation).toString());
+
+    console.log("✅ Skim-Sync 校验通过 Tx:", tx);
+  });
+
+  it("Rejects expired deadline", async () => {
+    const user = loadUser();
+    const deadline = Math.floor(Date.now() / 1000) - 600;
+
+    let errorCode = "";
+    try {
+      await program.methods.exchange(
+        true,
+        new anchor.BN(1_000_000),
+        new anchor.BN(0),
+        new anchor.BN(deadline)
+      ).accounts({
+        user: user.publicKey,
+        swap: swapPda,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+    } catch (e) {
+      errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+    }
+    expect(errorCode).to.equal("DeadlineExceeded");
+  });
+
+  it("Is observations", async () => {
+    const user = loadUser();
+    const [observationsPda] = PublicKey.findProgramAddressSync(
+      [swapPda.toBuffer(), Buffer.from("observations")],
+      program.programId
+    );
+    // 账户数据：8 字节判别符 + 56 字节头部，之后每个观测点 64 字节，开头为 i64 时间戳
+    const OBSERVATION_OFFSET = 64;
+    const OBSERVATION_SIZE = 64;
+    const slotTimestamp = async (index: number) => {
+      const info = await connection.getAccountInfo(observationsPda);
+      return info.data.readBigInt64LE(OBSERVATION_OFFSET + index * OBSERVATION_SIZE);
+    };
+
+    // 观测账户随池子创建，之前的存入、提取已写入观测点
+    let observations = await program.account.observations.fetch(observationsPda);
+    expect(observations.swap.toBase58()).to.equal(swapPda.toBase58());
+    expect(observations.cardinality).to.equal(1);
+    expect(observations.cardinalityNext).to.equal(1);
+    let info = await connection.getAccountInfo(observationsPda);
+    expect(info.data.length).to.equal(OBSERVATION_OFFSET + OBSERVATION_SIZE);
+
+    // 扩容到 8 个槽位，账户随之重新分配，新槽位在下一次写入时启用
+    await program.methods.increaseObservationCardinality(8).accounts({
+      payer: payer,
+      swap: swapPda,
+    }).rpc();
+
+    observations = await program.account.observations.fetch(observationsPda);
+    expect(observations.cardinalityNext).to.equal(8);
+    info = await connection.getAccountInfo(observationsPda);
+    expect(info.data.length).to.equal(OBSERVATION_OFFSET + 8 * OBSERVATION_SIZE);
+    expect(info.lamports).to.equal(await connection.getMinimumBalanceForRentExemption(info.data.length));
+
+    // 兑换写入观测点
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+    const tx = await program.methods.exchange(
+      false,
+      new anchor.BN(1_000_000),
+      new anchor.BN(0),
+      null                  // deadline
+    ).accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    observations = await program.account.observations.fetch(observationsPda);
+    expect(observations.cardinality).to.equal(8);
+    expect(observations.index).to.equal(1);
+    let swapAccount = await program.account.swap.fetch(swapPda);
+    expect((await slotTimestamp(1)).toString()).to.equal(swapAccount.blockTimestampLast.toString());
+
+    // 存入同样写入观测点
+    await new Promise((resolve) => setTimeout(resolve, 1500));
+    await program.methods.deposit(
+      new anchor.BN(1_000),
+      new anchor.BN(1_000_000_000),
+      new anchor.BN(1_000_000_000),
+      null                  // deadline
+    ).accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    observations = await program.account.observations.fetch(observationsPda);
+    expect(observations.index).to.equal(2);
+    swapAccount = await program.account.swap.fetch(swapPda);
+    expect((await slotTimestamp(2)).toString()).to.equal(swapAccount.blockTimestampLast.toString());
+
+    console.log("✅ Observations 校验通过 Tx:", tx);
+  });
+
   it("Is Swap", async () => {
     const user = loadUser();
     //--------------------------------------------------------------------
@@ -556,12 +1356,16 @@ describe("easy-amm", () => {
     const tx = await program.methods.exchange(
       true,
       new anchor.BN(amount_in.toString()),
-      new anchor.BN(minimum_amount_out.toString())
+      new anchor.BN(minimum_amount_out.toString()),
+      null                  // deadline
     ).accounts({
       user: user.publicKey,
+      swap: swapPda,
       tokenAMint: mintA,
       tokenBMint: mintB,
-      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
     }).signers([user]).rpc();
 
     // --------------------------------------------------------------------
@@ -584,10 +1388,832 @@ describe("easy-amm", () => {
     // 4. 用户 tokenB 余额应增加 destination_amount_swapped
     expect(newUserTokBInfo.amount).to.equal(oldUserTokB + destination_amount_swapped);
 
+    // 5. TWAP：时钟前进 Δt 后再次兑换，累计价格按兑换前的储备增加 (reserve_b << 64) / reserve_a · Δt
+    const big = (v: anchor.BN) => BigInt(v.toString());
+    const before = await program.account.swap.fetch(swapPda);
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+    await program.methods.exchange(true, new anchor.BN(1_000), new anchor.BN(0), null)
+      .accounts({
+        user: user.publicKey,
+        swap: swapPda,
+        tokenAMint: mintA,
+        tokenBMint: mintB,
+        poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+        lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+      }).signers([user]).rpc();
+    const after = await program.account.swap.fetch(swapPda);
+    const elapsed = big(after.blockTimestampLast) - big(before.blockTimestampLast);
+    const priceA = (big(before.reserveB) << BigInt(64)) / big(before.reserveA);
+    const priceB = (big(before.reserveA) << BigInt(64)) / big(before.reserveB);
+    expect(elapsed > BigInt(0)).to.be.true;
+    expect(big(after.priceACumulativeLast) - big(before.priceACumulativeLast)).to.equal(priceA * elapsed);
+    expect(big(after.priceBCumulativeLast) - big(before.priceBCumulativeLast)).to.equal(priceB * elapsed);
+
     console.log("✅ Swap 校验通过 Tx:", tx);
 
   });
 
+  it("Is Swap exact out", async () => {
+    const user = loadUser();
+    //--------------------------------------------------------------------
+    // 0. 读取旧状态
+    //--------------------------------------------------------------------
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const oldUserTokB = (await getAccount(connection, userTokenB)).amount;
+
+    const reserveA = BigInt((await getAccount(connection, tokenAPda)).amount);
+    const reserveB = BigInt((await getAccount(connection, tokenBPda)).amount);
+
+    //--------------------------------------------------------------------
+    // 1. 想要精确得到的 tokenB 数量，反推需要支付的 tokenA
+    //--------------------------------------------------------------------
+    const amountOut = BigInt(10_000_000);
+    const TRADE_FEE_BPS = BigInt(200);
+    const FEE_DENOM = BigInt(10_000);
+
+    const K = reserveA * reserveB;
+    const newB = reserveB - amountOut;
+    const newA = (K + newB - BigInt(1)) / newB;
+    const sourceLessFee = newA - reserveA;
+    const denominator = FEE_DENOM - TRADE_FEE_BPS;
+    const sourceIn = (sourceLessFee * FEE_DENOM + denominator - BigInt(1)) / denominator;
+    console.log("sourceIn: ", sourceIn);
+
+    // 允许 1% 滑点
+    const maximumAmountIn = sourceIn * BigInt(101) / BigInt(100);
+
+    const tx = await program.methods.exchangeExactOut(
+      true,
+      new anchor.BN(amountOut.toString()),
+      new anchor.BN(maximumAmountIn.toString()),
+      null                  // deadline
+    ).accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      poolFeeAccount: await getAssociatedTokenAddress(poolMint, payer),
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    //--------------------------------------------------------------------
+    // 2. 读取新状态并断言
+    //--------------------------------------------------------------------
+    const poolTokAAfter = await getAccount(connection, tokenAPda);
+    const poolTokBAfter = await getAccount(connection, tokenBPda);
+    const newUserTokAInfo = await getAccount(connection, userTokenA);
+    const newUserTokBInfo = await getAccount(connection, userTokenB);
+
+    expect(poolTokAAfter.amount).to.equal(reserveA + sourceIn);
+    expect(poolTokBAfter.amount).to.equal(reserveB - amountOut);
+    expect(newUserTokAInfo.amount).to.equal(oldUserTokA - sourceIn);
+    expect(newUserTokBInfo.amount).to.equal(oldUserTokB + amountOut);
+
+    console.log("✅ Swap-Exact-Out 校验通过 Tx:", tx);
+  });
+
+  it("Is Swap with owner fee", async () => {
+    const user = loadUser();
+    const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
+    const OWNER_TRADE_FEE_BPS = 100;
+    const HOST_FEE_BPS = 5_000;   // 推荐方分得一半协议手续费
+    const hostFeeAccount = await getAssociatedTokenAddress(poolMint, user.publicKey);
+
+    await program.methods.updateFees(200, 300, OWNER_TRADE_FEE_BPS, HOST_FEE_BPS, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    const oldFeeLp = (await getAccount(connection, poolFeeAccount)).amount;
+    const oldHostLp = (await getAccount(connection, hostFeeAccount)).amount;
+    const oldSupply = (await getMint(connection, poolMint)).supply;
+
+    const tx = await program.methods.exchange(
+      false,
+      new anchor.BN(50_000_000),
+      new anchor.BN(0),
+      null                  // deadline
+    ).accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      poolFeeAccount,
+      hostFeeAccount,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    // 协议手续费以池币形式铸造给 pool_fee_account，推荐方分得 host_fees 比例
+    const newFeeLp = (await getAccount(connection, poolFeeAccount)).amount;
+    const newHostLp = (await getAccount(connection, hostFeeAccount)).amount;
+    const newSupply = (await getMint(connection, poolMint)).supply;
+    const ownerMinted = newFeeLp - oldFeeLp;
+    const hostMinted = newHostLp - oldHostLp;
+    expect(ownerMinted > BigInt(0)).to.be.true;
+    expect(hostMinted).to.equal((ownerMinted + hostMinted) * BigInt(HOST_FEE_BPS) / BigInt(10_000));
+    expect(newSupply - oldSupply).to.equal(ownerMinted + hostMinted);
+
+    await program.methods.updateFees(200, 300, 0, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    console.log("✅ Swap-Owner-Fee 校验通过 Tx:", tx);
+  });
+
+  it("Is Swap with host fee", async () => {
+    const user = loadUser();
+    const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
+    const OWNER_TRADE_FEE_BPS = 100;
+    const HOST_FEE_BPS = 3_000;
+    // 推荐方为第三方账户，与用户和管理员都不同
+    const host = Keypair.generate();
+    const hostFeeAccount = (await getOrCreateAssociatedTokenAccount(connection, user, poolMint, host.publicKey)).address;
+
+    await program.methods.updateFees(200, 300, OWNER_TRADE_FEE_BPS, HOST_FEE_BPS, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    const accounts = {
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      poolFeeAccount,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    };
+
+    // 传入推荐方账户：协议池币按 host_fees 拆分，推荐方向下取整
+    let oldFeeLp = (await getAccount(connection, poolFeeAccount)).amount;
+    let oldSupply = (await getMint(connection, poolMint)).supply;
+    await program.methods.exchange(true, new anchor.BN(20_000_000), new anchor.BN(0), null)
+      .accounts({ ...accounts, hostFeeAccount })
+      .signers([user]).rpc();
+
+    const ownerMinted = (await getAccount(connection, poolFeeAccount)).amount - oldFeeLp;
+    const hostMinted = (await getAccount(connection, hostFeeAccount)).amount;
+    const totalMinted = (await getMint(connection, poolMint)).supply - oldSupply;
+    expect(hostMinted > BigInt(0)).to.be.true;
+    expect(totalMinted).to.equal(ownerMinted + hostMinted);
+    expect(hostMinted).to.equal(totalMinted * BigInt(HOST_FEE_BPS) / BigInt(10_000));
+
+    // 不传推荐方账户：协议池币全部归 pool_fee_account
+    oldFeeLp = (await getAccount(connection, poolFeeAccount)).amount;
+    oldSupply = (await getMint(connection, poolMint)).supply;
+    const tx = await program.methods.exchange(false, new anchor.BN(10_000_000), new anchor.BN(0), null)
+      .accounts({ ...accounts, hostFeeAccount: null })
+      .signers([user]).rpc();
+
+    const ownerOnly = (await getAccount(connection, poolFeeAccount)).amount - oldFeeLp;
+    expect(ownerOnly > BigInt(0)).to.be.true;
+    expect((await getMint(connection, poolMint)).supply - oldSupply).to.equal(ownerOnly);
+    expect((await getAccount(connection, hostFeeAccount)).amount).to.equal(hostMinted);
+
+    await program.methods.updateFees(200, 300, 0, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    console.log("✅ Swap-Host-Fee 校验通过 Tx:", tx);
+  });
+
+  it("Is flash loan", async () => {
+    const user = loadUser();
+    const FLASH_FEE_BPS = 30;
+    await program.methods.updateFees(200, 300, 0, 0, FLASH_FEE_BPS).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    const reserveA = (await getAccount(connection, tokenAPda)).amount;
+    const reserveB = (await getAccount(connection, tokenBPda)).amount;
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const amountA = BigInt(10_000_000);
+    const feeA = amountA * BigInt(FLASH_FEE_BPS) / BigInt(10_000);
+
+    const accounts = {
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      userTokenA,
+      userTokenB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    };
+
+    // 没有归还指令时借款失败
+    let failed = false;
+    try {
+      await program.methods.flashLoan(new anchor.BN(amountA.toString()), new anchor.BN(0))
+        .accounts({ ...accounts, instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY })
+        .signers([user]).rpc();
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+
+    const repayIx = await program.methods.repayFlashLoan().accounts(accounts).instruction();
+    const tx = await program.methods.flashLoan(new anchor.BN(amountA.toString()), new anchor.BN(0))
+      .accounts({ ...accounts, instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY })
+      .postInstructions([repayIx])
+      .signers([user]).rpc();
+
+    // 手续费留在池子中，用户只付出手续费
+    expect((await getAccount(connection, tokenAPda)).amount).to.equal(reserveA + feeA);
+    expect((await getAccount(connection, tokenBPda)).amount).to.equal(reserveB);
+    expect((await getAccount(connection, userTokenA)).amount).to.equal(oldUserTokA - feeA);
+    const swapAccount = await program.account.swap.fetch(swapPda);
+    expect(swapAccount.flashLoanLocked).to.be.false;
+
+    await program.methods.updateFees(200, 300, 0, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    console.log("✅ Flash-Loan 校验通过 Tx:", tx);
+  });
+
+  it("Is flash swap", async () => {
+    const user = loadUser();
+    const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
+    const OWNER_TRADE_FEE_BPS = 100;
+    await program.methods.updateFees(200, 300, OWNER_TRADE_FEE_BPS, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    const reserveA = (await getAccount(connection, tokenAPda)).amount;
+    const reserveB = (await getAccount(connection, tokenBPda)).amount;
+    const oldUserTokB = (await getAccount(connection, userTokenB)).amount;
+    const oldFeeLp = (await getAccount(connection, poolFeeAccount)).amount;
+
+    // 先拿到 token_b，再在回调中用 token 程序把 token_a 转入金库，手续费为 LP + 协议手续费
+    const amountOut = BigInt(1_000_000);
+    const fee = BigInt(200 + OWNER_TRADE_FEE_BPS);
+    const amountIn = (reserveA * amountOut * BigInt(10_000)) / ((reserveB - amountOut) * (BigInt(10_000) - fee)) + BigInt(2);
+    const payIx = createTransferInstruction(userTokenA, tokenAPda, user.publicKey, amountIn);
+
+    const accounts = {
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      poolFeeAccount,
+      userTokenA,
+      userTokenB,
+      callbackProgram: TOKEN_PROGRAM_ID,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    };
+
+    // 回调支付不足时失败
+    let failed = false;
+    try {
+      const underpayIx = createTransferInstruction(userTokenA, tokenAPda, user.publicKey, amountIn / BigInt(2));
+      await program.methods.flashSwap(true, new anchor.BN(amountOut.toString()), underpayIx.data)
+        .accounts(accounts)
+        .remainingAccounts(underpayIx.keys)
+        .signers([user]).rpc();
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+
+    // 回调不能借 repay_flash_loan 解除闪电兑换的锁
+    const repayIx = await program.methods.repayFlashLoan().accounts({
+      user: user.publicKey,
+      swap: swapPda,
+      tokenAMint: mintA,
+      tokenBMint: mintB,
+      userTokenA,
+      userTokenB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).instruction();
+    let logs = "";
+    try {
+      await program.methods.flashSwap(true, new anchor.BN(amountOut.toString()), repayIx.data)
+        .accounts({ ...accounts, callbackProgram: program.programId })
+        .remainingAccounts(repayIx.keys)
+        .signers([user]).rpc();
+    } catch (e) {
+      logs = ((e as anchor.AnchorError).logs ?? []).join("\n");
+    }
+    expect(logs).to.include("FlashLoanNotActive");
+
+    // 回调期间不能修改池子账户，否则结算时会被回调前的数据覆盖
+    const adminIxs = [
+      await program.methods.updateFees(500, 300, 0, 0, 0).accounts({ admin: payer, swap: swapPda }).instruction(),
+      await program.methods.setPause(1).accounts({ admin: payer, swap: swapPda }).instruction(),
+      await program.methods.setAdmin(user.publicKey).accounts({ admin: payer, swap: swapPda }).instruction(),
+    ];
+    for (const adminIx of adminIxs) {
+      logs = "";
+      try {
+        await program.methods.flashSwap(true, new anchor.BN(amountOut.toString()), adminIx.data)
+          .accounts({ ...accounts, callbackProgram: program.programId })
+          .remainingAccounts(adminIx.keys)
+          .signers([user]).rpc();
+      } catch (e) {
+        logs = ((e as anchor.AnchorError).logs ?? []).join("\n");
+      }
+      expect(logs).to.include("PoolLocked");
+    }
+    const swapState = await program.account.swap.fetch(swapPda);
+    expect(swapState.tradeFees).to.equal(200);
+    expect(swapState.admin.equals(payer)).to.be.true;
+
+    const tx = await program.methods.flashSwap(true, new anchor.BN(amountOut.toString()), payIx.data)
+      .accounts(accounts)
+      .remainingAccounts(payIx.keys)
+      .signers([user]).rpc();
+
+    expect((await getAccount(connection, tokenAPda)).amount).to.equal(reserveA + amountIn);
+    expect((await getAccount(connection, tokenBPda)).amount).to.equal(reserveB - amountOut);
+    expect((await getAccount(connection, userTokenB)).amount).to.equal(oldUserTokB + amountOut);
+    expect((await program.account.swap.fetch(swapPda)).flashLoanLocked).to.be.false;
+    // 协议手续费以池币形式铸造给 pool_fee_account
+    expect((await getAccount(connection, poolFeeAccount)).amount > oldFeeLp).to.be.true;
+
+    await program.methods.updateFees(200, 300, 0, 0, 0).accounts({
+      admin: payer,
+      swap: swapPda,
+    }).rpc();
+
+    console.log("✅ Flash-Swap 校验通过 Tx:", tx);
+  });
+
+  it("Is route swap", async () => {
+    const user = loadUser();
+
+    // 新建 token C 以及 B/C 池子，路由 A -> B -> C
+    const mintC = await createMint(connection, user, user.publicKey, null, 6);
+    const userTokenC = (await getOrCreateAssociatedTokenAccount(connection, user, mintC, user.publicKey)).address;
+    await mintTo(connection, user, mintC, userTokenC, user, 1_000_000_000);
+
+    const [pairA, pairB] = Buffer.compare(mintB.toBuffer(), mintC.toBuffer()) < 0 ? [mintB, mintC] : [mintC, mintB];
+    const [swap2] = PublicKey.findProgramAddressSync(
+      [Buffer.from("easy-amm"), pairA.toBuffer(), pairB.toBuffer()],
+      program.programId
+    );
+    const [swap2TokenA] = PublicKey.findProgramAddressSync([swap2.toBuffer(), Buffer.from("token_a")], program.programId);
+    const [swap2TokenB] = PublicKey.findProgramAddressSync([swap2.toBuffer(), Buffer.from("token_b")], program.programId);
+    const [poolMint2] = PublicKey.findProgramAddressSync([swap2.toBuffer(), Buffer.from("lp_mint")], program.programId);
+
+    await program.methods.initializeSwap(
+      200, 300, 0, 0, 0,
+      new anchor.BN(50_000_000),
+      new anchor.BN(50_000_000),
+      0,
+      new anchor.BN(0),
+      6,
+      null
+    ).accounts({
+      payer: payer,
+      user: user.publicKey,
+      tokenAMint: pairA,
+      tokenBMint: pairB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    const [swap2VaultB, swap2VaultC] = pairA.equals(mintB) ? [swap2TokenA, swap2TokenB] : [swap2TokenB, swap2TokenA];
+    const remainingAccounts = [
+      ...routeHop(swapPda, tokenAPda, tokenBPda, mintA, mintB, poolMint, await getAssociatedTokenAddress(poolMint, payer)),
+      ...routeHop(swap2, swap2VaultB, swap2VaultC, mintB, mintC, poolMint2, await getAssociatedTokenAddress(poolMint2, payer)),
+    ];
+
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const oldUserTokB = (await getAccount(connection, userTokenB)).amount;
+    const oldUserTokC = (await getAccount(connection, userTokenC)).amount;
+    const amountIn = new anchor.BN(1_000_000);
+
+    // 最终输出不足时整笔失败
+    let failed = false;
+    try {
+      await program.methods.routeSwap(2, amountIn, new anchor.BN(1_000_000), null)
+        .accounts({ user: user.publicKey, userSourceToken: userTokenA, userDestinationToken: userTokenC, tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID, lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID })
+        .remainingAccounts(remainingAccounts)
+        .signers([user]).rpc();
+    } catch (e) {
+      failed = true;
+    }
+    expect(failed).to.be.true;
+
+    const tx = await program.methods.routeSwap(2, amountIn, new anchor.BN(1), null)
+      .accounts({ user: user.publicKey, userSourceToken: userTokenA, userDestinationToken: userTokenC, tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID, lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID })
+      .remainingAccounts(remainingAccounts)
+      .signers([user]).rpc();
+
+    // 中间代币 B 不经过用户账户
+    expect((await getAccount(connection, userTokenA)).amount).to.equal(oldUserTokA - BigInt(1_000_000));
+    expect((await getAccount(connection, userTokenB)).amount).to.equal(oldUserTokB);
+    expect((await getAccount(connection, userTokenC)).amount > oldUserTokC).to.be.true;
+
+    console.log("✅ Route-Swap 校验通过 Tx:", tx);
+  });
+
+  it("Is stable swap", async () => {
+    const user = loadUser();
+    const tokenProgram = anchor.utils.token.TOKEN_PROGRAM_ID;
+    const RESERVE = 1_000_000_000_000;
+    const AMOUNT = 100_000_000_000;       // 储备的 10%
+    const stableX = await createMint(connection, user, user.publicKey, null, 6);
+    const stableY = await createMint(connection, user, user.publicKey, null, 6);
+    await fundUser(user, stableX, 10 * RESERVE, tokenProgram);
+    await fundUser(user, stableY, 10 * RESERVE, tokenProgram);
+
+    // amp_factor 超出 [1, 1_000_000] 时拒绝创建
+    for (const ampFactor of [0, 1_000_001]) {
+      let errorCode = "";
+      try {
+        await createPool(user, stableX, stableY, RESERVE, 1, ampFactor, tokenProgram);
+      } catch (e) {
+        errorCode = (e as anchor.AnchorError).error?.errorCode?.code;
+      }
+      expect(errorCode).to.equal("InvalidAmpFactor");
+    }
+
+    const pool = await createPool(user, stableX, stableY, RESERVE, 1, 100, tokenProgram);
+    const accounts = {
+      user: user.publicKey,
+      swap: pool.swap,
+      tokenAMint: pool.tokenAMint,
+      tokenBMint: pool.tokenBMint,
+      poolFeeAccount: pool.poolFeeAccount,
+      tokenProgram,
+      lpTokenProgram: tokenProgram
+    };
+    const userTokA = getAssociatedTokenAddressSync(pool.tokenAMint, user.publicKey);
+    const userTokB = getAssociatedTokenAddressSync(pool.tokenBMint, user.publicKey);
+    const userLp = getAssociatedTokenAddressSync(pool.lpMint, user.publicKey);
+
+    // 兑换：锚定币对在储备 10% 的交易量下仍接近 1:1，恒定乘积曲线只能得到约 89%
+    const oldUserTokB = (await getAccount(connection, userTokB)).amount;
+    await program.methods.exchange(true, new anchor.BN(AMOUNT), new anchor.BN(1), null)
+      .accounts(accounts)
+      .signers([user]).rpc();
+    const out = Number((await getAccount(connection, userTokB)).amount - oldUserTokB);
+    expect(out).to.be.greaterThan(AMOUNT * 0.97);
+    expect(out).to.be.lessThan(AMOUNT * 0.98);
+
+    // 双币存入：按储备比例转入两侧代币
+    const reserveA = (await getAccount(connection, pool.tokenA)).amount;
+    const reserveB = (await getAccount(connection, pool.tokenB)).amount;
+    const supply = (await getMint(connection, pool.lpMint)).supply;
+    const poolTokenAmount = BigInt(10_000_000_000);
+    const maxTokenA = poolTokenAmount * reserveA / supply * BigInt(101) / BigInt(100);
+    const maxTokenB = poolTokenAmount * reserveB / supply * BigInt(101) / BigInt(100);
+    const oldUserLp = (await getAccount(connection, userLp)).amount;
+    await program.methods.deposit(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(maxTokenA.toString()),
+      new anchor.BN(maxTokenB.toString()),
+      null
+    ).accounts(accounts).signers([user]).rpc();
+    expect((await getAccount(connection, userLp)).amount).to.equal(oldUserLp + poolTokenAmount);
+    const depositedA = (await getAccount(connection, pool.tokenA)).amount - reserveA;
+    const depositedB = (await getAccount(connection, pool.tokenB)).amount - reserveB;
+    expect(depositedA > BigInt(0) && depositedA <= maxTokenA).to.be.true;
+    expect(depositedB > BigInt(0) && depositedB <= maxTokenB).to.be.true;
+    // 两侧存入比例与储备比例一致(误差来自向上取整)
+    const ratioDiff = depositedA * reserveB - depositedB * reserveA;
+    expect((ratioDiff < BigInt(0) ? -ratioDiff : ratioDiff) <= reserveA + reserveB).to.be.true;
+
+    // 双币提取：扣除 3% 提取手续费后按储备比例取回两侧代币
+    const oldUserTokA = (await getAccount(connection, userTokA)).amount;
+    const oldUserTokB2 = (await getAccount(connection, userTokB)).amount;
+    const tx = await program.methods.withdrawAll(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(depositedA.toString()).muln(95).divn(100),
+      new anchor.BN(depositedB.toString()).muln(95).divn(100),
+      null
+    ).accounts(accounts).signers([user]).rpc();
+    expect((await getAccount(connection, userLp)).amount).to.equal(oldUserLp);
+    expect((await getAccount(connection, userTokA)).amount > oldUserTokA).to.be.true;
+    expect((await getAccount(connection, userTokB)).amount > oldUserTokB2).to.be.true;
+
+    console.log("✅ Stable-Swap 校验通过 Tx:", tx);
+  });
+
+  it("Is transfer fee swap", async () => {
+    const user = loadUser();
+    const tokenProgram = TOKEN_2022_PROGRAM_ID;
+    const FEE_BPS = 100;                  // 1% 转账手续费
+    const transferFee = (amount: bigint) => (amount * BigInt(FEE_BPS) + BigInt(9_999)) / BigInt(10_000);
+    const feeMint = await createMint2022(user, [ExtensionType.TransferFeeConfig], (mint) => [
+      createInitializeTransferFeeConfigInstruction(
+        mint, user.publicKey, user.publicKey, FEE_BPS, BigInt(1_000_000_000_000_000), TOKEN_2022_PROGRAM_ID
+      ),
+    ]);
+    const otherMint = await createMint2022(user, []);
+    const userFee = await fundUser(user, feeMint, 10_000_000_000_000, tokenProgram);
+    const userOther = await fundUser(user, otherMint, 10_000_000_000_000, tokenProgram);
+
+    const pool = await createPool(user, feeMint, otherMint, 1_000_000_000_000, 0, 0, tokenProgram);
+    const feeIsA = pool.tokenAMint.equals(feeMint);
+    const [feeVault, otherVault] = feeIsA ? [pool.tokenA, pool.tokenB] : [pool.tokenB, pool.tokenA];
+    const accounts = {
+      user: user.publicKey,
+      swap: pool.swap,
+      tokenAMint: pool.tokenAMint,
+      tokenBMint: pool.tokenBMint,
+      poolFeeAccount: pool.poolFeeAccount,
+      tokenProgram,
+      lpTokenProgram: tokenProgram
+    };
+    const balance = async (account: PublicKey) => (await getAccount(connection, account, "confirmed", tokenProgram)).amount;
+    const userLp = getAssociatedTokenAddressSync(pool.lpMint, user.publicKey, false, tokenProgram);
+
+    // 精确输入：池子只收到扣除转账手续费后的数量
+    const amountIn = BigInt(1_000_000_000);
+    let userFeeBefore = await balance(userFee);
+    let feeVaultBefore = await balance(feeVault);
+    await program.methods.exchange(feeIsA, new anchor.BN(amountIn.toString()), new anchor.BN(1), null)
+      .accounts(accounts)
+      .signers([user]).rpc({ commitment: "confirmed" });
+    expect(userFeeBefore - await balance(userFee)).to.equal(amountIn);
+    expect(await balance(feeVault) - feeVaultBefore).to.equal(amountIn - transferFee(amountIn));
+
+    // 精确输出：池子多转出转账手续费，用户正好收到 amount_out
+    const amountOut = BigInt(500_000_000);
+    userFeeBefore = await balance(userFee);
+    feeVaultBefore = await balance(feeVault);
+    await program.methods.exchangeExactOut(!feeIsA, new anchor.BN(amountOut.toString()), new anchor.BN(1_000_000_000), null)
+      .accounts(accounts)
+      .signers([user]).rpc({ commitment: "confirmed" });
+    const feeVaultOut = feeVaultBefore - await balance(feeVault);
+    expect(await balance(userFee) - userFeeBefore).to.equal(amountOut);
+    expect(feeVaultOut - transferFee(feeVaultOut)).to.equal(amountOut);
+
+    // 双币存入：用户多付转账手续费，池子收到按储备比例计算的数量
+    const poolTokenAmount = BigInt(10_000_000_000);
+    userFeeBefore = await balance(userFee);
+    feeVaultBefore = await balance(feeVault);
+    const otherVaultBefore = await balance(otherVault);
+    const supply = (await getMint(connection, pool.lpMint, "confirmed", tokenProgram)).supply;
+    const lpBefore = await balance(userLp);
+    await program.methods.deposit(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(1_000_000_000_000),
+      new anchor.BN(1_000_000_000_000),
+      null
+    ).accounts(accounts).signers([user]).rpc({ commitment: "confirmed" });
+    const depositedFee = await balance(feeVault) - feeVaultBefore;
+    const paidFee = userFeeBefore - await balance(userFee);
+    expect(await balance(userLp) - lpBefore).to.equal(poolTokenAmount);
+    expect(depositedFee).to.equal((poolTokenAmount * feeVaultBefore + supply - BigInt(1)) / supply);
+    expect(paidFee - transferFee(paidFee)).to.equal(depositedFee);
+    expect(await balance(otherVault) - otherVaultBefore).to.equal((poolTokenAmount * otherVaultBefore + supply - BigInt(1)) / supply);
+
+    // 双币提取：用户收到池子转出数量扣除转账手续费后的部分
+    userFeeBefore = await balance(userFee);
+    const userOtherBefore = await balance(userOther);
+    feeVaultBefore = await balance(feeVault);
+    const tx = await program.methods.withdrawAll(
+      new anchor.BN(poolTokenAmount.toString()),
+      new anchor.BN(1),
+      new anchor.BN(1),
+      null
+    ).accounts(accounts).signers([user]).rpc({ commitment: "confirmed" });
+    const withdrawnFee = feeVaultBefore - await balance(feeVault);
+    expect(await balance(userFee) - userFeeBefore).to.equal(withdrawnFee - transferFee(withdrawnFee));
+    expect(await balance(userOther) > userOtherBefore).to.be.true;
+    expect(await balance(userLp)).to.equal(lpBefore);
+
+    console.log("✅ Transfer-Fee-Swap 校验通过 Tx:", tx);
+  });
+
+  it("Is transfer hook swap", async () => {
+    const user = loadUser();
+    const tokenProgram = TOKEN_2022_PROGRAM_ID;
+
+    // 三个带转账钩子的 mint，每次转账对应 mint 的计数器加一
+    const createHookMint = async () => {
+      const mint = await createMint2022(user, [ExtensionType.TransferHook], (mint) => [
+        createInitializeTransferHookInstruction(mint, user.publicKey, hookProgram.programId, TOKEN_2022_PROGRAM_ID),
+      ]);
+      await hookProgram.methods.initializeExtraAccountMetaList()
+        .accounts({ payer: user.publicKey, mint })
+        .signers([user]).rpc();
+      await fundUser(user, mint, 10_000_000_000_000, tokenProgram);
+      const [extraAccountMetaList] = PublicKey.findProgramAddressSync(
+        [Buffer.from("extra-account-metas"), mint.toBuffer()],
+        hookProgram.programId
+      );
+      const [counter] = PublicKey.findProgramAddressSync([Buffer.from("counter"), mint.toBuffer()], hookProgram.programId);
+      return { mint, extraAccountMetaList, counter };
+    };
+    const hookX = await createHookMint();
+    const hookY = await createHookMint();
+    const hookZ = await createHookMint();
+
+    // remaining accounts 同时包含所有 mint 的钩子账户，池子按 mint 解析各自所需的账户
+    const remainingAccounts = [hookX, hookY, hookZ].flatMap(({ extraAccountMetaList, counter }) => [
+      { pubkey: extraAccountMetaList, isSigner: false, isWritable: false },
+      { pubkey: counter, isSigner: false, isWritable: true },
+    ]).concat([{ pubkey: hookProgram.programId, isSigner: false, isWritable: false }]);
+    const counts = async () => Promise.all(
+      [hookX, hookY, hookZ].map(async ({ counter }) => (await hookProgram.account.counter.fetch(counter)).count.toNumber())
+    );
+
+    const pool = await createPool(user, hookX.mint, hookY.mint, 1_000_000_000_000, 0, 0, tokenProgram, remainingAccounts);
+    expect(await counts()).to.deep.equal([1, 1, 0]);
+    const accounts = {
+      user: user.publicKey,
+      swap: pool.swap,
+      tokenAMint: pool.tokenAMint,
+      tokenBMint: pool.tokenBMint,
+      poolFeeAccount: pool.poolFeeAccount,
+      tokenProgram,
+      lpTokenProgram: tokenProgram
+    };
+
+    // 兑换：用户 → 池子、池子 → 用户各触发一次钩子
+    await program.methods.exchange(true, new anchor.BN(1_000_000_000), new anchor.BN(1), null)
+      .accounts(accounts)
+      .remainingAccounts(remainingAccounts)
+      .signers([user]).rpc();
+    expect(await counts()).to.deep.equal([2, 2, 0]);
+
+    // 双币提取：两侧各转出一次(LP 手续费转账不带钩子)
+    const tx = await program.methods.withdrawAll(
+      new anchor.BN(1_000_000_000),
+      new anchor.BN(1),
+      new anchor.BN(1),
+      null
+    ).accounts(accounts)
+      .remainingAccounts(remainingAccounts)
+      .signers([user]).rpc();
+    expect(await counts()).to.deep.equal([3, 3, 0]);
+
+    // 路由 X -> Y -> Z：钩子账户放在各跳账户之后，三次转账各触发一次对应 mint 的钩子
+    const pool2 = await createPool(user, hookY.mint, hookZ.mint, 1_000_000_000_000, 0, 0, tokenProgram, remainingAccounts);
+    expect(await counts()).to.deep.equal([3, 4, 1]);
+    const vaults = (p: Awaited<ReturnType<typeof createPool>>, source: PublicKey) =>
+      p.tokenAMint.equals(source) ? [p.tokenA, p.tokenB] : [p.tokenB, p.tokenA];
+    const [pool1Source, pool1Destination] = vaults(pool, hookX.mint);
+    const [pool2Source, pool2Destination] = vaults(pool2, hookY.mint);
+    const routeAccounts = [
+      ...routeHop(pool.swap, pool1Source, pool1Destination, hookX.mint, hookY.mint, pool.lpMint, pool.poolFeeAccount),
+      ...routeHop(pool2.swap, pool2Source, pool2Destination, hookY.mint, hookZ.mint, pool2.lpMint, pool2.poolFeeAccount),
+    ];
+    const userTokenZ = getAssociatedTokenAddressSync(hookZ.mint, user.publicKey, false, tokenProgram);
+    const oldUserTokZ = (await getAccount(connection, userTokenZ, "confirmed", tokenProgram)).amount;
+    await program.methods.routeSwap(2, new anchor.BN(1_000_000_000), new anchor.BN(1), null)
+      .accounts({
+        user: user.publicKey,
+        userSourceToken: getAssociatedTokenAddressSync(hookX.mint, user.publicKey, false, tokenProgram),
+        userDestinationToken: userTokenZ,
+        tokenProgram,
+        lpTokenProgram: tokenProgram
+      })
+      .remainingAccounts([...routeAccounts, ...remainingAccounts])
+      .signers([user]).rpc({ commitment: "confirmed" });
+    expect(await counts()).to.deep.equal([4, 5, 2]);
+    expect((await getAccount(connection, userTokenZ, "confirmed", tokenProgram)).amount > oldUserTokZ).to.be.true;
+
+    // 缺少钩子账户时转账失败
+    let rejected = false;
+    try {
+      await program.methods.exchange(true, new anchor.BN(1_000_000), new anchor.BN(1), null)
+        .accounts(accounts)
+        .signers([user]).rpc();
+    } catch (e) {
+      rejected = true;
+    }
+    expect(rejected).to.be.true;
+
+    console.log("✅ Transfer-Hook-Swap 校验通过 Tx:", tx);
+  });
+
+  it("Is interest-bearing stable swap", async () => {
+    const user = loadUser();
+    const RATE = 32_767;                  // 年化利率上限(基点)
+    const AMOUNT = 100_000_000_000;
+    const interestMint = await createMint2022(user, [ExtensionType.InterestBearingConfig], (mint) => [
+      createInitializeInterestBearingMintInstruction(mint, user.publicKey, RATE, TOKEN_2022_PROGRAM_ID),
+    ]);
+    const plainMint = await createMint2022(user, []);
+    const quoteMint = await createMint2022(user, []);
+    await fundUser(user, interestMint, 1_000_000_000_000_000, TOKEN_2022_PROGRAM_ID);
+    await fundUser(user, plainMint, 1_000_000_000_000_000, TOKEN_2022_PROGRAM_ID);
+    const userQuote = await fundUser(user, quoteMint, 1_000_000_000_000_000, TOKEN_2022_PROGRAM_ID);
+
+    // 两个储备相同的 StableSwap 池子，只有一个池子的代币计息
+    const interestPool = await createPool(user, interestMint, quoteMint, 100_000_000_000_000, 1, 1_000, TOKEN_2022_PROGRAM_ID);
+    const plainPool = await createPool(user, plainMint, quoteMint, 100_000_000_000_000, 1, 1_000, TOKEN_2022_PROGRAM_ID);
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+
+    const swapToQuote = async (pool: Awaited<ReturnType<typeof createPool>>, mint: PublicKey) => {
+      const before = (await getAccount(connection, userQuote, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+      const sig = await program.methods.exchange(
+        pool.tokenAMint.equals(mint),
+        new anchor.BN(AMOUNT),
+        new anchor.BN(0),
+        null                  // deadline
+      ).accounts({
+        user: user.publicKey,
+        swap: pool.swap,
+        tokenAMint: pool.tokenAMint,
+        tokenBMint: pool.tokenBMint,
+        poolFeeAccount: pool.poolFeeAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        lpTokenProgram: TOKEN_2022_PROGRAM_ID
+      }).signers([user]).rpc({ commitment: "confirmed" });
+      const after = (await getAccount(connection, userQuote, "confirmed", TOKEN_2022_PROGRAM_ID)).amount;
+      return { out: Number(after - before), sig };
+    };
+    const { out: interestOut, sig } = await swapToQuote(interestPool, interestMint);
+    const { out: plainOut } = await swapToQuote(plainPool, plainMint);
+
+    // 计息代币按 e^(r·t) 换算成含息数量后定价，多出的输出约为 amount·(1 - 2%)·(scale - 1)
+    const blockTime = (await connection.getTransaction(sig, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0
+    })).blockTime;
+    const config = getInterestBearingMintConfigState(
+      await getMint(connection, interestMint, "confirmed", TOKEN_2022_PROGRAM_ID)
+    );
+    const elapsed = blockTime - Number(config.initializationTimestamp);
+    const scale = Math.exp(RATE * elapsed / 31_556_736 / 10_000);
+    const expectedExtra = AMOUNT * 0.98 * (scale - 1);
+    expect(elapsed).to.be.greaterThan(0);
+    expect(interestOut - plainOut).to.be.greaterThan(expectedExtra * 0.95);
+    expect(interestOut - plainOut).to.be.lessThan(expectedExtra * 1.05);
+  });
+
+  it("Is native SOL swap", async () => {
+    const user = loadUser();
+
+    // 新建 A/SOL 池子，初始 SOL 由用户预先包装
+    const wsolAta = await createWrappedNativeAccount(connection, user, user.publicKey, 10_000_000);
+    const [pairA, pairB] = Buffer.compare(mintA.toBuffer(), NATIVE_MINT.toBuffer()) < 0 ? [mintA, NATIVE_MINT] : [NATIVE_MINT, mintA];
+    const [swap3] = PublicKey.findProgramAddressSync(
+      [Buffer.from("easy-amm"), pairA.toBuffer(), pairB.toBuffer()],
+      program.programId
+    );
+    const [poolMint3] = PublicKey.findProgramAddressSync([swap3.toBuffer(), Buffer.from("lp_mint")], program.programId);
+
+    await program.methods.initializeSwap(
+      200, 300, 0, 0, 0,
+      new anchor.BN(10_000_000),
+      new anchor.BN(10_000_000),
+      0,
+      new anchor.BN(0),
+      6,
+      null
+    ).accounts({
+      payer: payer,
+      user: user.publicKey,
+      tokenAMint: pairA,
+      tokenBMint: pairB,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    }).signers([user]).rpc();
+
+    const accounts = {
+      user: user.publicKey,
+      swap: swap3,
+      tokenAMint: pairA,
+      tokenBMint: pairB,
+      poolFeeAccount: await getAssociatedTokenAddress(poolMint3, payer),
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      lpTokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID
+    };
+
+    // A -> SOL：用户事先已有的 wSOL 账户不关闭，收到的 SOL 以 wSOL 留在账户中
+    await program.methods.exchange(pairA.equals(mintA), new anchor.BN(1_000_000), new anchor.BN(1), null)
+      .accounts(accounts)
+      .signers([user]).rpc();
+    expect((await getAccount(connection, wsolAta)).amount > BigInt(0)).to.be.true;
+
+    // 用户自行解包后，后续兑换在指令内创建 wSOL 账户
+    await closeAccount(connection, user, wsolAta, user.publicKey, user);
+
+    // SOL -> A：无需预先包装，指令内创建 wSOL 账户并从用户转入 lamports，结束时关闭退回租金
+    const oldUserTokA = (await getAccount(connection, userTokenA)).amount;
+    const tx = await program.methods.exchange(pairA.equals(NATIVE_MINT), new anchor.BN(1_000_000), new anchor.BN(1), null)
+      .accounts(accounts)
+      .signers([user]).rpc();
+    expect((await getAccount(connection, userTokenA)).amount > oldUserTokA).to.be.true;
+    expect(await connection.getAccountInfo(wsolAta)).to.be.null;
+
+    // A -> SOL：指令内新建的 wSOL 账户在结束时关闭，lamports 直接退回用户
+    const lamportsBefore = await connection.getBalance(user.publicKey);
+    await program.methods.exchange(pairA.equals(mintA), new anchor.BN(1_000_000), new anchor.BN(1), null)
+      .accounts(accounts)
+      .signers([user]).rpc();
+    expect(await connection.getAccountInfo(wsolAta)).to.be.null;
+    expect(await connection.getBalance(user.publicKey)).to.be.greaterThan(lamportsBefore);
+
+    console.log("✅ Native-SOL-Swap 校验通过 Tx:", tx);
+  });
+
   it("Withdraws all remaining LP", async () => {
     const user = loadUser();
     const poolFeeAccount = await getAssociatedTokenAddress(poolMint, payer);
@@ -628,10 +2254,12 @@ describe("easy-amm", () => {
       .withdrawAll(
         new anchor.BN(poolTokenAmount.toString()),
         new anchor.BN(minTokenA.toString()),
-        new anchor.BN(minTokenB.toString())
+        new anchor.BN(minTokenB.toString()),
+        null
       )
       .accounts({
         user: user.publicKey,
+        swap: swapPda,
         tokenAMint: mintA,
         tokenBMint: mintB,
         poolFeeAccount,
@@ -661,8 +2289,10 @@ describe("easy-amm", () => {
     // LP 总供应量减少应等于有效销毁量
     expect(poolMintInfoAfter.supply).to.equal(poolSupply - poolTokenAmount2);
 
-    // 用户 LP 余额应为 0
+    // 用户 LP 余额应为 0，锁定的 MINIMUM_LIQUIDITY 仍留在池子中
     expect(userLpInfoAfter.amount).to.equal(BigInt(0));
+    expect(poolMintInfoAfter.supply > BigInt(0)).to.be.true;
+    expect(tokenAAccountAfter.amount > BigInt(0) && tokenBAccountAfter.amount > BigInt(0)).to.be.true;
 
     console.log("✅ Withdraw-All-Remaining 校验通过 Tx:", tx);
   });